@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+
+use crate::config::Config;
+
+const EMBEDDED_DENYLIST: &str = include_str!("alias_filter/denylist.txt");
+
+/// Rejects offensive short links and the ones colliding with names of the service.
+pub struct AliasFilter {
+    exact: HashSet<String>,
+    substrings: Vec<String>,
+}
+
+impl AliasFilter {
+    pub fn new(denylist: &str) -> Self {
+        let mut filter = AliasFilter {
+            exact: HashSet::new(),
+            substrings: vec![],
+        };
+        filter.extend(denylist);
+        filter
+    }
+
+    /// Custom denylist extends the embedded one
+    pub fn from_config(config: &Config) -> std::io::Result<Self> {
+        let mut filter = AliasFilter::new(EMBEDDED_DENYLIST);
+        if let Some(path) = &config.denylist_file {
+            filter.extend(&std::fs::read_to_string(path)?);
+        }
+        Ok(filter)
+    }
+
+    fn extend(&mut self, denylist: &str) {
+        for line in denylist.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.strip_prefix('=') {
+                Some(word) => {
+                    self.exact.insert(word.to_lowercase());
+                }
+                None => self.substrings.push(line.to_lowercase()),
+            }
+        }
+    }
+
+    pub fn is_allowed(&self, alias: &str) -> bool {
+        !deleet(&alias.to_lowercase()).into_iter().any(|reading| {
+            // `s_h_i_t` should not slip through
+            let whole = reading.replace('_', "");
+            std::iter::once(whole.as_str())
+                .chain(reading.split('_'))
+                .any(|part| self.exact.contains(part))
+                || self.substrings.iter().any(|word| whole.contains(word))
+        })
+    }
+}
+
+// `1` is ambiguous between `i` and `l`, so both readings are returned
+fn deleet(alias: &str) -> [String; 2] {
+    let read = |one: char| {
+        alias
+            .chars()
+            .map(|c| match c {
+                '0' => 'o',
+                '1' | '!' | '|' => one,
+                '3' => 'e',
+                '4' | '@' => 'a',
+                '5' | '$' => 's',
+                '7' => 't',
+                '8' => 'b',
+                '9' => 'g',
+                c => c,
+            })
+            .collect()
+    };
+    [read('i'), read('l')]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_denylist() {
+        let filter = AliasFilter::from_config(&Config::default()).unwrap();
+
+        assert!(filter.is_allowed("brave_otter_42"));
+        assert!(filter.is_allowed("grape_juice"));
+        assert!(filter.is_allowed("peacock"));
+        assert!(filter.is_allowed("statuses"));
+        assert!(filter.is_allowed("Ab3dK9xZ"));
+
+        assert!(!filter.is_allowed("status"));
+        assert!(!filter.is_allowed("METRICS"));
+        assert!(!filter.is_allowed("4dm1n"));
+        assert!(!filter.is_allowed("admin_panel"));
+        assert!(!filter.is_allowed("sh1tstorm"));
+        assert!(!filter.is_allowed("bull_sh_it"));
+        assert!(!filter.is_allowed("xxFUCKxx"));
+        assert!(!filter.is_allowed("brave_r4pe_42"));
+    }
+
+    #[test]
+    fn test_custom_denylist() {
+        let filter = AliasFilter::new("# comment\n=docs\nacme\n");
+        assert!(!filter.is_allowed("docs"));
+        assert!(filter.is_allowed("docsearch"));
+        assert!(!filter.is_allowed("the_4cme_way"));
+        assert!(filter.is_allowed("status"));
+
+        let config = Config {
+            denylist_file: Some("/nonexistent/denylist.txt".to_string()),
+            ..Default::default()
+        };
+        assert!(AliasFilter::from_config(&config).is_err());
+    }
+}
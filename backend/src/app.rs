@@ -4,13 +4,46 @@ use axum::{Router, middleware, routing::get};
 use std::sync::Arc;
 use tokio::net::TcpListener;
 
-use crate::config::Config;
-use crate::link_generator::LinkGenerator;
+use crate::alias_filter::AliasFilter;
+use crate::canonical::UrlCanonicalizer;
+use crate::clock::Clock;
+use crate::config::{Config, ConfigError};
+use crate::domain_policy::DomainPolicy;
+use crate::domains::Domains;
+use crate::interstitial::ContinueSigner;
+use crate::key_pool::{self, KeyPool};
+use crate::link_generator::{Generators, LinkGenerator};
+use crate::link_unwrapper::LinkUnwrapper;
+use crate::params::ParamTemplates;
+use crate::password::AttemptLimiter;
+use crate::preview::PreviewFetcher;
+use crate::reloading;
+use crate::resources::Resources;
 use crate::storage::Storage;
+use crate::targeting::GeoIp;
+use crate::validation::UrlValidator;
 
 pub struct AppState {
     pub storage: Storage,
+    pub url_validator: UrlValidator,
+    pub url_canonicalizer: UrlCanonicalizer,
+    pub domain_policy: Option<DomainPolicy>,
+    pub domains: Option<Domains>,
+    pub link_unwrapper: LinkUnwrapper,
+    pub param_templates: Option<ParamTemplates>,
     pub link_generator: LinkGenerator,
+    pub generators: Generators,
+    pub preview_fetcher: Option<PreviewFetcher>,
+    pub alias_filter: AliasFilter,
+    pub key_pool: Option<KeyPool>,
+    pub password_attempts: AttemptLimiter,
+    pub continue_signer: ContinueSigner,
+    pub clock: Arc<Clock>,
+    pub geoip: Option<GeoIp>,
+    pub trust_forwarded_for: bool,
+    pub admin_token: Option<String>,
+    pub public_base_url: Option<String>,
+    pub metrics_link_labels: bool,
 }
 
 pub struct App {
@@ -19,15 +52,70 @@ pub struct App {
 }
 
 impl App {
-    pub async fn from_config(config: &Config) -> Self {
+    /// Loads the files the settings point to, configs which did not come from `Config::load`
+    /// have not been checked yet
+    #[allow(dead_code)]
+    pub async fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let resources = Resources::load(config).map_err(ConfigError)?;
+        Ok(App::new(config, resources, Arc::new(Clock::System)).await)
+    }
+
+    /// Links are scheduled and created by `clock`, tests pass a manual one to step through time
+    pub async fn new(config: &Config, resources: Resources, clock: Arc<Clock>) -> Self {
+        let Resources {
+            domains,
+            domain_policy,
+            param_templates,
+            alias_filter,
+            geoip,
+            link_generator,
+        } = resources;
+        // Pooled keys are random, so the pool would bypass any other generator
+        let key_pool = match link_generator {
+            LinkGenerator::Random => KeyPool::from_config(config).await,
+            _ => None,
+        };
         let state = Arc::new(AppState {
-            link_generator: LinkGenerator::from_config(config),
+            link_generator,
+            generators: Generators::from_config(config),
             storage: Storage::from_config(config).await,
+            url_validator: UrlValidator::from_config(config),
+            url_canonicalizer: UrlCanonicalizer::from_config(config),
+            domain_policy,
+            link_unwrapper: LinkUnwrapper::from_config(config, domains.as_ref()),
+            domains,
+            param_templates,
+            preview_fetcher: PreviewFetcher::from_config(config),
+            alias_filter,
+            key_pool,
+            password_attempts: AttemptLimiter::from_config(config),
+            continue_signer: ContinueSigner::random(),
+            clock,
+            geoip,
+            trust_forwarded_for: config.trust_forwarded_for,
+            admin_token: config.admin_token.clone(),
+            public_base_url: config.public_base_url.clone(),
+            metrics_link_labels: config.metrics_link_labels,
         });
+        key_pool::spawn_refill(state.clone());
+        if state.domain_policy.is_some() || state.param_templates.is_some() {
+            reloading::spawn_reload(state.clone(), |state| {
+                if let Some(policy) = &state.domain_policy {
+                    policy.reload_if_changed();
+                }
+                if let Some(templates) = &state.param_templates {
+                    templates.reload_if_changed();
+                }
+            });
+        }
 
-        let router = handlers::api::v1::router()
-            .route("/status", get(handlers::status))
-            .route("/metrics", get(metrics::metrics_handler))
+        let mut router = handlers::api::v1::router()
+            .merge(handlers::admin::router())
+            .route("/status", get(handlers::status));
+        if !config.metrics_disabled {
+            router = router.route("/metrics", get(metrics::metrics_handler));
+        }
+        let router = router
             .layer(middleware::from_fn(MetricsMiddleware::record))
             .with_state(state.clone());
 
@@ -44,10 +132,14 @@ impl App {
     pub async fn run(self) {
         let addr = self.listener.local_addr().expect("Cannot get local addr");
         log::info!("Starting to accept clients on {addr}");
-        axum::serve(self.listener, self.router)
-            .with_graceful_shutdown(shutdown_signal())
-            .await
-            .unwrap();
+        axum::serve(
+            self.listener,
+            self.router
+                .into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
     }
 }
 
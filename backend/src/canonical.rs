@@ -0,0 +1,171 @@
+use url::{Host, Url};
+
+use crate::config::Config;
+
+// Exact names, `utm_*` ones are matched by the prefix
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "igshid", "mc_cid",
+    "mc_eid", "_hsenc", "_hsmi",
+];
+
+/// Brings equivalent urls to a single form, so they are stored and compared the same way.
+///
+/// Parsing already lowercases scheme and host, drops default ports, resolves dot segments and
+/// converts IDN hosts to punycode. On top of that the trailing dot of the host is dropped,
+/// percent-encoding is normalized and, optionally, tracking parameters are removed from the query.
+pub struct UrlCanonicalizer {
+    strip_tracking_params: bool,
+}
+
+impl UrlCanonicalizer {
+    pub fn new(strip_tracking_params: bool) -> Self {
+        UrlCanonicalizer {
+            strip_tracking_params,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        UrlCanonicalizer::new(config.strip_tracking_params)
+    }
+
+    /// Returns `None` for urls which cannot be parsed
+    pub fn canonicalize(&self, url: &str) -> Option<String> {
+        let mut url = Url::parse(url).ok()?;
+
+        if let Some(Host::Domain(domain)) = url.host()
+            && let Some(domain) = domain.strip_suffix('.')
+        {
+            let domain = domain.to_string();
+            url.set_host(Some(&domain)).ok()?;
+        }
+
+        let path = normalize_percent_encoding(url.path());
+        url.set_path(&path);
+
+        let query = url.query().map(|query| {
+            query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .filter(|pair| !self.strip_tracking_params || !is_tracking_param(pair))
+                .map(normalize_percent_encoding)
+                .collect::<Vec<_>>()
+                .join("&")
+        });
+        url.set_query(query.as_deref().filter(|query| !query.is_empty()));
+
+        if let Some(fragment) = url.fragment() {
+            let fragment = normalize_percent_encoding(fragment);
+            url.set_fragment(Some(&fragment));
+        }
+
+        Some(url.into())
+    }
+}
+
+/// Lowercased host of the url, without the trailing dot: `example.com.` is the same host as
+/// `example.com`, so rules and own domains must match both
+pub fn host(url: &Url) -> Option<String> {
+    let host = url.host_str()?.to_lowercase();
+    Some(match host.strip_suffix('.') {
+        Some(host) => host.to_string(),
+        None => host,
+    })
+}
+
+fn is_tracking_param(pair: &str) -> bool {
+    let name = pair
+        .split('=')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    name.starts_with("utm_") || TRACKING_PARAMS.contains(&name.as_str())
+}
+
+// Escaped unreserved characters are decoded (`%7E` -> `~`), other escapes are uppercased
+fn normalize_percent_encoding(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut normalized = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => {
+                normalized.push(byte as char);
+                i += 3;
+            }
+            Some(byte) => {
+                normalized.push_str(&format!("%{byte:02X}"));
+                i += 3;
+            }
+            None => {
+                let c = text[i..]
+                    .chars()
+                    .next()
+                    .expect("Index is on a char boundary");
+                normalized.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonicalize() {
+        let canonicalizer = UrlCanonicalizer::new(false);
+        let canonical = |url: &str| canonicalizer.canonicalize(url).unwrap();
+
+        assert!(canonical("HTTP://Example.com:80/a/../b") == "http://example.com/b");
+        assert!(canonical("http://example.com/b") == "http://example.com/b");
+        assert!(canonical("https://example.com:443") == "https://example.com/");
+        assert!(canonical("https://example.com:8443/") == "https://example.com:8443/");
+        assert!(canonical("https://example.com/./a/%2e%2E/b/") == "https://example.com/b/");
+        assert!(canonical("https://bücher.example/") == "https://xn--bcher-kva.example/");
+        assert!(canonical("https://Example.com./a") == "https://example.com/a");
+        assert!(canonical("https://example.com.:8443/") == "https://example.com:8443/");
+        assert!(
+            canonical("https://example.com/%7euser/%e2%82%ac")
+                == "https://example.com/~user/%E2%82%AC"
+        );
+        assert!(
+            canonical("https://example.com/?q=a%2fb&&x=%41#%7e")
+                == "https://example.com/?q=a%2Fb&x=A#~"
+        );
+        assert!(canonical("https://example.com/?") == "https://example.com/");
+        assert!(
+            canonical("https://example.com/?utm_source=x&id=1")
+                == "https://example.com/?utm_source=x&id=1"
+        );
+
+        assert!(canonicalizer.canonicalize("example.com").is_none());
+    }
+
+    #[test]
+    fn test_host() {
+        let host = |url: &str| host(&Url::parse(url).unwrap());
+        assert!(host("https://Evil.Example./x").as_deref() == Some("evil.example"));
+        assert!(host("https://evil.example/").as_deref() == Some("evil.example"));
+        assert!(host("http://127.0.0.1/").as_deref() == Some("127.0.0.1"));
+        assert!(host("mailto:user@example.com").is_none());
+    }
+
+    #[test]
+    fn test_strip_tracking_params() {
+        let canonicalizer = UrlCanonicalizer::new(true);
+        let canonical = |url: &str| canonicalizer.canonicalize(url).unwrap();
+
+        assert!(
+            canonical("https://example.com/page?utm_source=mail&id=1&UTM_Medium=x&fbclid=abc")
+                == "https://example.com/page?id=1"
+        );
+        assert!(canonical("https://example.com/?gclid=1&utm_campaign") == "https://example.com/");
+        assert!(canonical("https://example.com/?utmost=1") == "https://example.com/?utmost=1");
+    }
+}
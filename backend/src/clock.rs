@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current time, a manual one lets tests fast-forward.
+pub enum Clock {
+    System,
+    Manual(AtomicU64),
+}
+
+impl Clock {
+    /// Seconds since the Unix epoch
+    pub fn now(&self) -> u64 {
+        match self {
+            Clock::System => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            Clock::Manual(now) => now.load(Ordering::SeqCst),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn manual(start: u64) -> Self {
+        Clock::Manual(AtomicU64::new(start))
+    }
+
+    #[allow(dead_code)]
+    pub fn advance(&self, by: std::time::Duration) {
+        match self {
+            Clock::System => panic!("System clock cannot be advanced"),
+            Clock::Manual(now) => {
+                now.fetch_add(by.as_secs(), Ordering::SeqCst);
+            }
+        }
+    }
+}
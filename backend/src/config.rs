@@ -1,31 +1,662 @@
-use std::env;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 
-#[derive(Default)]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::resources::Resources;
+
+const CONFIG_FILE_FLAG: &str = "config";
+const CONFIG_FILE_ENV: &str = "RURL_CONFIG_FILE";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneratorKind {
+    Random,
+    Wordlist,
+    Llm,
+}
+
+impl std::str::FromStr for GeneratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(GeneratorKind::Random),
+            "wordlist" => Ok(GeneratorKind::Wordlist),
+            // `openrouter` is kept for compatibility with older deployments
+            "llm" | "openrouter" => Ok(GeneratorKind::Llm),
+            _ => Err(format!("Unknown generator kind: {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LlmProvider {
+    /// Any OpenAI-compatible chat completions API: openrouter, OpenAI, llama.cpp server etc.
+    #[default]
+    OpenAi,
+    /// Native Ollama chat API
+    Ollama,
+}
+
+impl std::str::FromStr for LlmProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openai" => Ok(LlmProvider::OpenAi),
+            "ollama" => Ok(LlmProvider::Ollama),
+            _ => Err(format!("Unknown LLM provider: {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct Config {
     pub port: u16,
     pub host: String,
 
     pub redis_endpoint: Option<String>,
-    pub openrouter_token: Option<String>,
+
+    pub generator: Option<GeneratorKind>,
+    pub wordlist_adjectives: Option<String>,
+    pub wordlist_nouns: Option<String>,
+
+    pub llm_provider: LlmProvider,
+    pub llm_base_url: Option<String>,
+    pub llm_model: Option<String>,
+    pub llm_temperature: Option<f32>,
+    pub llm_api_key: Option<String>,
+    pub llm_connect_timeout_ms: Option<u64>,
+    pub llm_read_timeout_ms: Option<u64>,
+    pub llm_max_retries: Option<u32>,
+    pub llm_candidates: Option<usize>,
+    pub llm_structured_output: bool,
+    pub llm_system_prompt_file: Option<String>,
+    pub llm_user_prompt_file: Option<String>,
+    pub llm_locale: Option<String>,
+
+    pub fetch_preview: bool,
+    pub preview_timeout_ms: Option<u64>,
+    pub preview_max_bytes: Option<usize>,
+
+    pub strip_tracking_params: bool,
+    pub allowed_schemes: Option<Vec<String>>,
+    pub max_url_length: Option<usize>,
+    // Inverted, so the derived default keeps private destinations blocked
+    pub allow_private_destinations: bool,
+    pub allowed_internal_hosts: Vec<String>,
+    pub domain_policy_file: Option<String>,
+    pub public_hosts: Vec<String>,
+    pub public_base_url: Option<String>,
+    pub domains_file: Option<String>,
+    pub expand_shorteners: bool,
+
+    pub param_templates_file: Option<String>,
+
+    pub password_max_attempts: Option<u32>,
+    pub password_max_global_attempts: Option<u32>,
+
+    pub geoip_database: Option<String>,
+    pub trust_forwarded_for: bool,
+
+    pub denylist_file: Option<String>,
+
+    pub key_pool_size: Option<usize>,
+    pub key_pool_low_water: Option<usize>,
+
+    pub admin_token: Option<String>,
+
+    pub metrics_disabled: bool,
+    /// Labels per-link metrics by the short link, one series per link
+    pub metrics_link_labels: bool,
 }
 
+/// Every invalid setting found at startup, one per line
+#[derive(Debug, Error)]
+#[error("Invalid configuration:\n  {}", .0.join("\n  "))]
+pub struct ConfigError(pub Vec<String>);
+
 impl Config {
-    pub fn from_env() -> Config {
-        let port: u16 = env::var("RURL_PORT")
-            .expect("Provide port via RURL_PORT")
-            .parse()
-            .expect("RURL_PORT is not a valid port");
+    /// Reads the config file from `--config` or `RURL_CONFIG_FILE`, environment variables and
+    /// CLI flags override its values. Files the settings point to are loaded as well.
+    pub fn load() -> Result<(Config, Resources), ConfigError> {
+        let env = std::env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        Config::from_sources(std::env::args().skip(1), env)
+    }
+
+    pub fn from_sources(
+        args: impl IntoIterator<Item = String>,
+        env: HashMap<String, String>,
+    ) -> Result<(Config, Resources), ConfigError> {
+        let mut sources = Sources::new(args, env);
+
+        let port = sources.required("server.port", "RURL_PORT", "port");
+        let host = sources.required("server.host", "RURL_HOST", "host");
+        let public_hosts = sources
+            .list("server.public_hosts", "RURL_PUBLIC_HOSTS")
+            .unwrap_or_default();
+        let public_base_url = sources
+            .string("server.public_base_url", "RURL_PUBLIC_BASE_URL")
+            .map(|url| url.trim_end_matches('/').to_string());
+        let domains_file = sources.string("server.domains_file", "RURL_DOMAINS_FILE");
+        let trust_forwarded_for =
+            sources.flag("server.trust_forwarded_for", "RURL_TRUST_FORWARDED_FOR");
+        let admin_token = sources
+            .string("server.admin_token", "RURL_ADMIN_TOKEN")
+            .filter(|token| !token.is_empty());
+
+        let redis_endpoint = sources.string("storage.redis_endpoint", "RURL_REDIS_ENDPOINT");
+
+        let generator = sources.parse("generator.kind", "RURL_GENERATOR", "generator");
+        let wordlist_adjectives =
+            sources.string("generator.wordlist_adjectives", "RURL_WORDLIST_ADJECTIVES");
+        let wordlist_nouns = sources.string("generator.wordlist_nouns", "RURL_WORDLIST_NOUNS");
+        let denylist_file = sources.string("generator.denylist_file", "RURL_DENYLIST_FILE");
+        let key_pool_size =
+            sources.parse("generator.key_pool_size", "RURL_KEY_POOL_SIZE", "number");
+        let key_pool_low_water = sources.parse(
+            "generator.key_pool_low_water",
+            "RURL_KEY_POOL_LOW_WATER",
+            "number",
+        );
+
+        let llm_provider = sources
+            .parse("llm.provider", "RURL_LLM_PROVIDER", "provider")
+            .unwrap_or_default();
+        let llm_base_url = sources.string("llm.base_url", "RURL_LLM_BASE_URL");
+        let llm_model = sources.string("llm.model", "RURL_LLM_MODEL");
+        let llm_temperature = sources.parse("llm.temperature", "RURL_LLM_TEMPERATURE", "number");
+        let llm_api_key = sources
+            .string("llm.api_key", "RURL_LLM_API_KEY")
+            .or_else(|| sources.env.get("RURL_OPENROUTER_TOKEN").cloned());
+        let llm_connect_timeout_ms = sources.parse(
+            "llm.connect_timeout_ms",
+            "RURL_LLM_CONNECT_TIMEOUT_MS",
+            "number",
+        );
+        let llm_read_timeout_ms =
+            sources.parse("llm.read_timeout_ms", "RURL_LLM_READ_TIMEOUT_MS", "number");
+        let llm_max_retries = sources.parse("llm.max_retries", "RURL_LLM_MAX_RETRIES", "number");
+        let llm_candidates = sources.parse("llm.candidates", "RURL_LLM_CANDIDATES", "number");
+        let llm_structured_output =
+            sources.flag("llm.structured_output", "RURL_LLM_STRUCTURED_OUTPUT");
+        let llm_system_prompt_file =
+            sources.string("llm.system_prompt_file", "RURL_LLM_SYSTEM_PROMPT_FILE");
+        let llm_user_prompt_file =
+            sources.string("llm.user_prompt_file", "RURL_LLM_USER_PROMPT_FILE");
+        let llm_locale = sources.string("llm.locale", "RURL_LLM_LOCALE");
+
+        let fetch_preview = sources.flag("preview.fetch", "RURL_FETCH_PREVIEW");
+        let preview_timeout_ms =
+            sources.parse("preview.timeout_ms", "RURL_PREVIEW_TIMEOUT_MS", "number");
+        let preview_max_bytes =
+            sources.parse("preview.max_bytes", "RURL_PREVIEW_MAX_BYTES", "number");
 
-        let host = env::var("RURL_HOST").expect("Provide host via RURL_HOST");
+        let strip_tracking_params = sources.flag(
+            "validation.strip_tracking_params",
+            "RURL_STRIP_TRACKING_PARAMS",
+        );
+        let allowed_schemes = sources
+            .list("validation.allowed_schemes", "RURL_ALLOWED_SCHEMES")
+            .map(|schemes| {
+                schemes
+                    .into_iter()
+                    .map(|scheme| scheme.to_lowercase())
+                    .collect()
+            });
+        let max_url_length =
+            sources.parse("validation.max_url_length", "RURL_MAX_URL_LENGTH", "number");
+        let allow_private_destinations = !sources
+            .parse(
+                "validation.block_private_destinations",
+                "RURL_BLOCK_PRIVATE_DESTINATIONS",
+                "bool",
+            )
+            .unwrap_or(true);
+        let allowed_internal_hosts = sources
+            .list(
+                "validation.allowed_internal_hosts",
+                "RURL_ALLOWED_INTERNAL_HOSTS",
+            )
+            .unwrap_or_default();
+        let domain_policy_file =
+            sources.string("validation.domain_policy_file", "RURL_DOMAIN_POLICY_FILE");
+        let expand_shorteners =
+            sources.flag("validation.expand_shorteners", "RURL_EXPAND_SHORTENERS");
 
-        let redis_endpoint = env::var("RURL_REDIS_ENDPOINT").ok();
-        let openrouter_token = env::var("RURL_OPENROUTER_TOKEN").ok();
+        let param_templates_file = sources.string(
+            "redirects.param_templates_file",
+            "RURL_PARAM_TEMPLATES_FILE",
+        );
+        let geoip_database = sources.string("redirects.geoip_database", "RURL_GEOIP_DATABASE");
 
-        Config {
-            port,
-            host,
+        let password_max_attempts = sources.parse(
+            "limits.password_max_attempts",
+            "RURL_PASSWORD_MAX_ATTEMPTS",
+            "number",
+        );
+        let password_max_global_attempts = sources.parse(
+            "limits.password_max_global_attempts",
+            "RURL_PASSWORD_MAX_GLOBAL_ATTEMPTS",
+            "number",
+        );
+
+        let metrics_disabled = !sources
+            .parse("metrics.enabled", "RURL_METRICS_ENABLED", "bool")
+            .unwrap_or(true);
+        let metrics_link_labels = sources.flag("metrics.link_labels", "RURL_METRICS_LINK_LABELS");
+
+        // Settings which are valid on their own, but not together
+        if let (Some(size), Some(low_water)) = (key_pool_size, key_pool_low_water)
+            && low_water > size
+        {
+            sources.errors.push(format!(
+                "generator.key_pool_low_water ({low_water}) must not exceed generator.key_pool_size ({size})"
+            ));
+        }
+        if llm_candidates == Some(0) {
+            sources
+                .errors
+                .push("llm.candidates must be at least 1".to_string());
+        }
+        if allowed_schemes.as_ref().is_some_and(Vec::is_empty) {
+            sources
+                .errors
+                .push("validation.allowed_schemes must list at least one scheme".to_string());
+        }
+        if let Some(url) = &public_base_url
+            && !url::Url::parse(url).is_ok_and(|url| !url.cannot_be_a_base())
+        {
+            sources
+                .errors
+                .push(format!("server.public_base_url {url} is not a valid url"));
+        }
+
+        let config = Config {
+            port: port.unwrap_or_default(),
+            host: host.unwrap_or_default(),
             redis_endpoint,
-            openrouter_token,
+            generator,
+            wordlist_adjectives,
+            wordlist_nouns,
+            llm_provider,
+            llm_base_url,
+            llm_model,
+            llm_temperature,
+            llm_api_key,
+            llm_connect_timeout_ms,
+            llm_read_timeout_ms,
+            llm_max_retries,
+            llm_candidates,
+            llm_structured_output,
+            llm_system_prompt_file,
+            llm_user_prompt_file,
+            llm_locale,
+            fetch_preview,
+            preview_timeout_ms,
+            preview_max_bytes,
+            strip_tracking_params,
+            allowed_schemes,
+            max_url_length,
+            allow_private_destinations,
+            allowed_internal_hosts,
+            domain_policy_file,
+            public_hosts,
+            public_base_url,
+            domains_file,
+            expand_shorteners,
+            param_templates_file,
+            password_max_attempts,
+            password_max_global_attempts,
+            geoip_database,
+            trust_forwarded_for,
+            denylist_file,
+            key_pool_size,
+            key_pool_low_water,
+            admin_token,
+            metrics_disabled,
+            metrics_link_labels,
+        };
+        // Files are loaded even when some settings are invalid, so all errors are reported at once
+        let resources = Resources::load(&config);
+        let mut errors = sources.finish();
+        match resources {
+            Ok(resources) if errors.is_empty() => Ok((config, resources)),
+            Ok(_) => Err(ConfigError(errors)),
+            Err(failures) => {
+                errors.extend(failures);
+                Err(ConfigError(errors))
+            }
         }
     }
 }
+
+/// Raw setting values by precedence: CLI flags, environment variables, then the config file.
+///
+/// Each setting has a dotted key in the file, e.g. `storage.redis_endpoint`, an environment
+/// variable `RURL_REDIS_ENDPOINT` and a flag named after it, `--redis-endpoint`. Errors are
+/// collected rather than returned, so all of them are reported at once.
+struct Sources {
+    args: HashMap<String, String>,
+    env: HashMap<String, String>,
+    file: HashMap<String, String>,
+    file_path: String,
+    used: HashSet<String>,
+    errors: Vec<String>,
+}
+
+impl Sources {
+    fn new(args: impl IntoIterator<Item = String>, env: HashMap<String, String>) -> Self {
+        let mut sources = Sources {
+            args: HashMap::new(),
+            env,
+            file: HashMap::new(),
+            file_path: String::new(),
+            used: HashSet::new(),
+            errors: vec![],
+        };
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                sources.errors.push(format!("Unexpected argument {arg}"));
+                continue;
+            };
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                // A flag without a value is a switched on bool
+                None => match args.next_if(|next| !next.starts_with("--")) {
+                    Some(value) => (flag.to_string(), value),
+                    None => (flag.to_string(), "true".to_string()),
+                },
+            };
+            sources.args.insert(name, value);
+        }
+
+        let path = sources
+            .args
+            .remove(CONFIG_FILE_FLAG)
+            .or_else(|| sources.env.get(CONFIG_FILE_ENV).cloned());
+        if let Some(path) = path {
+            match std::fs::read_to_string(&path) {
+                Ok(contents) => match toml::from_str::<toml::Table>(&contents) {
+                    Ok(table) => flatten(&mut sources.file, "", table),
+                    Err(e) => sources
+                        .errors
+                        .push(format!("Invalid config file {path}: {e}")),
+                },
+                Err(e) => sources
+                    .errors
+                    .push(format!("Cannot read config file {path}: {e}")),
+            }
+            sources.file_path = path;
+        }
+        sources
+    }
+
+    /// The value and where it comes from
+    fn raw(&mut self, key: &str, env: &str) -> Option<(String, String)> {
+        let flag = flag_name(env);
+        self.used.insert(flag.clone());
+        self.used.insert(key.to_string());
+        if let Some(value) = self.args.get(&flag) {
+            return Some((value.clone(), format!("--{flag}")));
+        }
+        if let Some(value) = self.env.get(env) {
+            return Some((value.clone(), env.to_string()));
+        }
+        self.file
+            .get(key)
+            .map(|value| (value.clone(), format!("{key} in {}", self.file_path)))
+    }
+
+    fn string(&mut self, key: &str, env: &str) -> Option<String> {
+        self.raw(key, env).map(|(value, _)| value)
+    }
+
+    fn parse<T: FromStr>(&mut self, key: &str, env: &str, what: &str) -> Option<T> {
+        let (value, origin) = self.raw(key, env)?;
+        value
+            .parse()
+            .inspect_err(|_| self.errors.push(format!("{origin} is not a valid {what}")))
+            .ok()
+    }
+
+    fn flag(&mut self, key: &str, env: &str) -> bool {
+        self.parse(key, env, "bool").unwrap_or(false)
+    }
+
+    /// Comma-separated in flags and environment, an array in the file
+    fn list(&mut self, key: &str, env: &str) -> Option<Vec<String>> {
+        self.string(key, env).map(|list| {
+            list.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+    }
+
+    fn required<T: FromStr>(&mut self, key: &str, env: &str, what: &str) -> Option<T> {
+        if self.raw(key, env).is_none() {
+            self.errors.push(format!(
+                "Provide {what} via {env}, --{} or {key} in the config file",
+                flag_name(env)
+            ));
+            return None;
+        }
+        self.parse(key, env, what)
+    }
+
+    /// Typos are reported instead of being silently ignored
+    fn finish(mut self) -> Vec<String> {
+        let mut unknown: Vec<_> = self
+            .args
+            .keys()
+            .filter(|flag| !self.used.contains(*flag))
+            .map(|flag| format!("Unknown flag --{flag}"))
+            .chain(
+                self.file
+                    .keys()
+                    .filter(|key| !self.used.contains(*key))
+                    .map(|key| format!("Unknown setting {key} in {}", self.file_path)),
+            )
+            .collect();
+        unknown.sort();
+        self.errors.extend(unknown);
+        self.errors
+    }
+}
+
+/// `RURL_REDIS_ENDPOINT` is set by `--redis-endpoint`
+fn flag_name(env: &str) -> String {
+    env.trim_start_matches("RURL_")
+        .to_lowercase()
+        .replace('_', "-")
+}
+
+/// File values are kept as text, so they are parsed the same way as the environment ones
+fn flatten(values: &mut HashMap<String, String>, prefix: &str, table: toml::Table) {
+    for (key, value) in table {
+        let key = format!("{prefix}{key}");
+        let text = match value {
+            toml::Value::Table(table) => {
+                flatten(values, &format!("{key}."), table);
+                continue;
+            }
+            toml::Value::String(value) => value,
+            toml::Value::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    toml::Value::String(item) => item,
+                    item => item.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            value => value.to_string(),
+        };
+        values.insert(key, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_layers() {
+        let path = std::env::temp_dir().join(format!("rurl-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+[server]
+port = 4444
+host = "0.0.0.0"
+public_hosts = ["sho.rt", "localhost:4444"]
+
+[storage]
+redis_endpoint = "redis:6379"
+
+[generator]
+kind = "wordlist"
+
+[llm]
+temperature = 0.5
+
+[metrics]
+enabled = false
+"#,
+        )
+        .unwrap();
+        let (config, _) = Config::from_sources(
+            args(&[
+                "--config",
+                &path.to_string_lossy(),
+                "--port=5555",
+                "--fetch-preview",
+            ]),
+            env(&[("RURL_REDIS_ENDPOINT", "localhost:6379"), ("HOME", "/root")]),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Flags override the environment, which overrides the file
+        assert!(config.port == 5555);
+        assert!(config.host == "0.0.0.0");
+        assert!(config.redis_endpoint.as_deref() == Some("localhost:6379"));
+        assert!(config.generator == Some(GeneratorKind::Wordlist));
+        assert!(config.llm_temperature == Some(0.5));
+        assert!(config.public_hosts == ["sho.rt", "localhost:4444"]);
+        assert!(config.fetch_preview);
+        assert!(config.metrics_disabled);
+        assert!(!config.strip_tracking_params);
+    }
+
+    #[test]
+    fn test_environment_only() {
+        let (config, _) = Config::from_sources(
+            args(&[]),
+            env(&[
+                ("RURL_PORT", "4444"),
+                ("RURL_HOST", "localhost"),
+                ("RURL_ALLOWED_SCHEMES", "HTTPS, mailto"),
+                ("RURL_OPENROUTER_TOKEN", "token"),
+            ]),
+        )
+        .unwrap();
+        assert!(config.port == 4444 && config.host == "localhost");
+        assert!(config.allowed_schemes == Some(vec!["https".into(), "mailto".into()]));
+        assert!(config.llm_api_key.as_deref() == Some("token"));
+        assert!(!config.metrics_disabled);
+        assert!(!config.allow_private_destinations);
+    }
+
+    #[test]
+    fn test_all_errors_reported() {
+        let path =
+            std::env::temp_dir().join(format!("rurl-config-bad-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+[server]
+port = "http"
+
+[storage]
+redis = "x"
+"#,
+        )
+        .unwrap();
+        let Err(ConfigError(errors)) = Config::from_sources(
+            args(&[
+                "--config",
+                &path.to_string_lossy(),
+                "--generator",
+                "magic",
+                "--colour",
+            ]),
+            env(&[("RURL_LLM_MAX_RETRIES", "-1")]),
+        ) else {
+            panic!("Invalid settings are accepted");
+        };
+        std::fs::remove_file(&path).unwrap();
+
+        let expected = [
+            format!(
+                "server.port in {} is not a valid port",
+                path.to_string_lossy()
+            ),
+            "Provide host via RURL_HOST, --host or server.host in the config file".to_string(),
+            "--generator is not a valid generator".to_string(),
+            "RURL_LLM_MAX_RETRIES is not a valid number".to_string(),
+            "Unknown flag --colour".to_string(),
+            format!(
+                "Unknown setting storage.redis in {}",
+                path.to_string_lossy()
+            ),
+        ];
+        assert!(errors == expected, "{errors:?}");
+
+        let Err(ConfigError(errors)) =
+            Config::from_sources(args(&["stray", "--config=/nonexistent.toml"]), env(&[]))
+        else {
+            panic!("Invalid arguments are accepted");
+        };
+        assert!(errors[0] == "Unexpected argument stray");
+        assert!(errors[1].starts_with("Cannot read config file /nonexistent.toml"));
+    }
+
+    #[test]
+    fn test_inconsistent_settings() {
+        let Err(ConfigError(errors)) = Config::from_sources(
+            args(&["--key-pool-size=10", "--key-pool-low-water=20"]),
+            env(&[
+                ("RURL_PORT", "4444"),
+                ("RURL_HOST", "localhost"),
+                ("RURL_LLM_CANDIDATES", "0"),
+                ("RURL_ALLOWED_SCHEMES", " , "),
+                ("RURL_PUBLIC_BASE_URL", "sho.rt"),
+                ("RURL_DENYLIST_FILE", "/nonexistent/denylist.txt"),
+            ]),
+        ) else {
+            panic!("Inconsistent settings are accepted");
+        };
+        assert!(errors.len() == 5, "{errors:?}");
+        assert!(errors[0].starts_with("generator.key_pool_low_water (20) must not exceed"));
+        assert!(errors[1] == "llm.candidates must be at least 1");
+        assert!(errors[2] == "validation.allowed_schemes must list at least one scheme");
+        assert!(errors[3] == "server.public_base_url sho.rt is not a valid url");
+        assert!(errors[4].starts_with("Cannot read denylist /nonexistent/denylist.txt"));
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use url::{Host, Url};
+
+use crate::config::Config;
+use crate::validation::UrlRejection;
+
+/// Keeps links and fetches away from loopback, private and cloud-metadata addresses.
+///
+/// Hosts are resolved and every address is checked, IP literals in any notation
+/// (`http://2130706433/`, `http://[::ffff:127.0.0.1]/`) are normalized by url parsing.
+pub struct DestinationGuard {
+    allowed_hosts: HashSet<String>,
+}
+
+impl DestinationGuard {
+    pub fn new(allowed_hosts: &[String]) -> Self {
+        DestinationGuard {
+            allowed_hosts: allowed_hosts
+                .iter()
+                .map(|host| host.trim_matches(['[', ']']).to_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Option<Arc<Self>> {
+        (!config.allow_private_destinations).then(|| DestinationGuard::for_fetches(config))
+    }
+
+    /// Fetches made by the service itself are always guarded, the setting only covers new links
+    pub fn for_fetches(config: &Config) -> Arc<Self> {
+        Arc::new(DestinationGuard::new(&config.allowed_internal_hosts))
+    }
+
+    fn is_allowed_host(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .contains(host.trim_matches(['[', ']']).to_lowercase().as_str())
+    }
+
+    pub async fn check(&self, url: &Url) -> Result<(), UrlRejection> {
+        let Some(host) = url.host() else {
+            return Ok(());
+        };
+        if self.is_allowed_host(&host.to_string()) {
+            return Ok(());
+        }
+        let addresses: Vec<IpAddr> = match host {
+            Host::Ipv4(ip) => vec![ip.into()],
+            Host::Ipv6(ip) => vec![ip.into()],
+            Host::Domain(domain) => {
+                let port = url.port_or_known_default().unwrap_or_default();
+                tokio::net::lookup_host((domain, port))
+                    .await
+                    .map_err(|_| UrlRejection::Unresolvable(domain.to_string()))?
+                    .map(|address| address.ip())
+                    .collect()
+            }
+        };
+        match addresses.into_iter().find(|&ip| is_forbidden(ip)) {
+            Some(ip) => Err(UrlRejection::PrivateAddress(ip)),
+            None => Ok(()),
+        }
+    }
+
+    /// Resolver is not called for IP literals, so redirects to them are checked here
+    pub fn is_forbidden_literal(&self, url: &Url) -> bool {
+        let ip: IpAddr = match url.host() {
+            Some(Host::Ipv4(ip)) => ip.into(),
+            Some(Host::Ipv6(ip)) => ip.into(),
+            _ => return false,
+        };
+        is_forbidden(ip) && !self.is_allowed_host(&ip.to_string())
+    }
+}
+
+// Rechecking at connection time closes the window for DNS rebinding
+impl Resolve for DestinationGuard {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allowed = self.is_allowed_host(name.as_str());
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if !allowed
+                && let Some(address) = addresses.iter().find(|address| is_forbidden(address.ip()))
+            {
+                return Err(UrlRejection::PrivateAddress(address.ip()).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub fn is_forbidden(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_forbidden_v4(ip),
+        IpAddr::V6(ip) => is_forbidden_v6(ip),
+    }
+}
+
+fn is_forbidden_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        // 169.254.169.254 is the metadata endpoint of most clouds
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network" 0.0.0.0/8
+        || a == 0
+        // Carrier-grade NAT 100.64.0.0/10, Alibaba metadata 100.100.100.200 is here too
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments 192.0.0.0/24
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        // Benchmarking 198.18.0.0/15
+        || (a == 198 && (18..20).contains(&b))
+        // Reserved 240.0.0.0/4
+        || a >= 240
+}
+
+fn is_forbidden_v6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_forbidden_v4(ip);
+    }
+    let segments = ip.segments();
+    // NAT64 64:ff9b::/96 embeds an IPv4 address
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., a, b, c, d] = ip.octets();
+        return is_forbidden_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || ip.is_unique_local()
+        || ip.is_unicast_link_local()
+        // Deprecated IPv4-compatible ::a.b.c.d
+        || segments[..6] == [0; 6]
+        // Documentation 2001:db8::/32
+        || segments[..2] == [0x2001, 0xdb8]
+        // Teredo 2001::/32 and 6to4 2002::/16 tunnel to arbitrary IPv4 addresses
+        || segments[..2] == [0x2001, 0]
+        || segments[0] == 0x2002
+        // Deprecated site-local fec0::/10
+        || segments[0] & 0xffc0 == 0xfec0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forbidden_addresses() {
+        let forbidden = |ip: &str| is_forbidden(ip.parse().unwrap());
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2001:0:4136:e378:8000:63bf:3fff:fdd2",
+            "2002:a9fe:a9fe::1",
+            "fec0::1",
+        ] {
+            assert!(forbidden(ip), "{ip} must be forbidden");
+        }
+        for ip in [
+            "93.184.216.34",
+            "8.8.8.8",
+            "100.128.0.1",
+            "172.32.0.1",
+            "2606:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(!forbidden(ip), "{ip} must be allowed");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let guard = DestinationGuard::new(&["internal.example".to_string(), "10.0.0.5".into()]);
+        let check = |url: &str| {
+            let url = Url::parse(url).unwrap();
+            let guard = &guard;
+            async move { guard.check(&url).await }
+        };
+
+        for url in [
+            "http://127.0.0.1/",
+            "http://2130706433/",
+            "http://0x7f.1/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://localhost:8080/",
+        ] {
+            assert!(
+                matches!(check(url).await, Err(UrlRejection::PrivateAddress(_))),
+                "{url} must be rejected"
+            );
+        }
+        assert!(check("http://93.184.216.34/").await.is_ok());
+        assert!(check("http://10.0.0.5/").await.is_ok());
+        assert!(check("http://internal.example/").await.is_ok());
+        assert!(matches!(
+            check("http://nonexistent.invalid/").await,
+            Err(UrlRejection::Unresolvable(_))
+        ));
+
+        assert!(guard.is_forbidden_literal(&Url::parse("http://[::1]/").unwrap()));
+        assert!(!guard.is_forbidden_literal(&Url::parse("http://10.0.0.5/").unwrap()));
+        assert!(!guard.is_forbidden_literal(&Url::parse("http://localhost/").unwrap()));
+    }
+}
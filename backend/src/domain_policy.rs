@@ -0,0 +1,255 @@
+use regex::Regex;
+use thiserror::Error;
+use url::Url;
+
+use crate::canonical;
+use crate::config::Config;
+use crate::reloading::{FileContents, ReloadingFile};
+use crate::validation::UrlRejection;
+
+#[derive(Debug, Error)]
+pub enum DomainPolicyError {
+    #[error("Cannot read domain policy {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid domain policy rule on line {line}: {rule}")]
+    InvalidRule { line: usize, rule: String },
+
+    #[error("Invalid regex on line {line}: {source}")]
+    Regex { line: usize, source: regex::Error },
+}
+
+enum Pattern {
+    Exact(String),
+    // Subdomains of the stored domain, not the domain itself
+    Wildcard(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Pattern::Exact(domain) => host == domain,
+            Pattern::Wildcard(domain) => host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|subdomain| subdomain.ends_with('.')),
+            Pattern::Regex(regex) => regex.is_match(host),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Rules {
+    block: Vec<Pattern>,
+    allow: Vec<Pattern>,
+    warn: Vec<Pattern>,
+}
+
+impl Rules {
+    fn parse(policy: &str) -> Result<Self, DomainPolicyError> {
+        let mut rules = Rules::default();
+        for (i, line) in policy.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || DomainPolicyError::InvalidRule {
+                line: i + 1,
+                rule: line.to_string(),
+            };
+            let (action, pattern) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let pattern = pattern.trim();
+            let pattern = if let Some(regex) = pattern.strip_prefix("re:") {
+                Pattern::Regex(
+                    Regex::new(regex).map_err(|source| DomainPolicyError::Regex {
+                        line: i + 1,
+                        source,
+                    })?,
+                )
+            } else if let Some(domain) = pattern.strip_prefix("*.") {
+                Pattern::Wildcard(domain.to_lowercase())
+            } else if !pattern.contains('*') {
+                Pattern::Exact(pattern.to_lowercase())
+            } else {
+                return Err(invalid());
+            };
+            match action {
+                "block" => rules.block.push(pattern),
+                "allow" => rules.allow.push(pattern),
+                "warn" => rules.warn.push(pattern),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+impl FileContents for Rules {
+    type Error = DomainPolicyError;
+
+    const NAME: &'static str = "domain policy";
+
+    fn read_error(path: &str, source: std::io::Error) -> DomainPolicyError {
+        DomainPolicyError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    fn parse(_path: &str, contents: &str) -> Result<Self, DomainPolicyError> {
+        Rules::parse(contents)
+    }
+}
+
+/// Blocked and allowed destination domains from a file, which is reloaded when it changes.
+///
+/// Each line is `block <pattern>`, `allow <pattern>` or `warn <pattern>`, where the pattern is an
+/// exact host, `*.example.com` for any subdomain or `re:<regex>`. Block rules win, and once there
+/// is any allow rule only the hosts matching one of them are accepted. Links to warned hosts work,
+/// but visitors see an interstitial page first.
+pub struct DomainPolicy {
+    rules: ReloadingFile<Rules>,
+}
+
+impl DomainPolicy {
+    pub fn from_config(config: &Config) -> Result<Option<Self>, DomainPolicyError> {
+        let Some(path) = &config.domain_policy_file else {
+            return Ok(None);
+        };
+        Ok(Some(DomainPolicy {
+            rules: ReloadingFile::open(path)?,
+        }))
+    }
+
+    pub fn reload_if_changed(&self) {
+        self.rules.reload_if_changed();
+    }
+
+    pub fn check(&self, url: &str) -> Result<(), UrlRejection> {
+        let Some(host) = Url::parse(url).ok().as_ref().and_then(canonical::host) else {
+            return Ok(());
+        };
+        let rules = self.rules.read();
+        if rules.block.iter().any(|pattern| pattern.matches(&host)) {
+            return Err(UrlRejection::DomainBlocked(host));
+        }
+        if !rules.allow.is_empty() && !rules.allow.iter().any(|pattern| pattern.matches(&host)) {
+            return Err(UrlRejection::DomainNotAllowed(host));
+        }
+        Ok(())
+    }
+
+    pub fn warns(&self, url: &str) -> bool {
+        let Some(host) = Url::parse(url).ok().as_ref().and_then(canonical::host) else {
+            return false;
+        };
+        let rules = self.rules.read();
+        rules.warn.iter().any(|pattern| pattern.matches(&host))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(rules: &str) -> DomainPolicy {
+        DomainPolicy {
+            rules: ReloadingFile::with_contents(Rules::parse(rules).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_block_rules() {
+        let policy = policy(
+            "# Known phishing\nblock evil.example\nblock *.phish.example\nblock re:^paypa[l1]-.*\\.com$\n",
+        );
+        assert!(policy.check("https://example.com/").is_ok());
+        assert!(policy.check("https://not-evil.example/").is_ok());
+        assert!(policy.check("https://phish.example/").is_ok());
+        assert!(policy.check("https://paypal.com/").is_ok());
+
+        assert!(policy.check("https://EVIL.example/path").is_err());
+        assert!(policy.check("https://evil.example./").is_err());
+        assert!(policy.check("https://login.phish.example./").is_err());
+        assert!(policy.check("https://login.phish.example/").is_err());
+        assert!(policy.check("https://a.b.phish.example/").is_err());
+        assert!(
+            policy.check("https://paypa1-secure.com/")
+                == Err(UrlRejection::DomainBlocked("paypa1-secure.com".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_allow_rules() {
+        let policy = policy("allow company.com\nallow *.company.com\nblock old.company.com\n");
+        assert!(policy.check("https://company.com/").is_ok());
+        assert!(policy.check("https://wiki.company.com/").is_ok());
+        assert!(policy.check("https://old.company.com/").is_err());
+        assert!(
+            policy.check("https://example.com/")
+                == Err(UrlRejection::DomainNotAllowed("example.com".to_string()))
+        );
+        assert!(policy.check("https://evilcompany.com/").is_err());
+        assert!(policy.check("https://wiki.company.com./").is_ok());
+        assert!(policy.check("https://old.company.com./").is_err());
+        assert!(policy.check("https://example.com./").is_err());
+    }
+
+    #[test]
+    fn test_warn_rules() {
+        let policy = policy("warn *.free-hosting.example\nwarn re:^xn--\n");
+        assert!(policy.check("https://site.free-hosting.example/").is_ok());
+        assert!(policy.warns("https://site.free-hosting.example/"));
+        assert!(policy.warns("https://site.free-hosting.example./"));
+        assert!(policy.warns("https://xn--80ak6aa92e.com/"));
+        assert!(!policy.warns("https://free-hosting.example/"));
+        assert!(!policy.warns("not a url"));
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(matches!(
+            Rules::parse("deny evil.example"),
+            Err(DomainPolicyError::InvalidRule { line: 1, .. })
+        ));
+        assert!(matches!(
+            Rules::parse("block ok.example\nblock evil.*"),
+            Err(DomainPolicyError::InvalidRule { line: 2, .. })
+        ));
+        assert!(matches!(
+            Rules::parse("block re:(unclosed"),
+            Err(DomainPolicyError::Regex { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_config() {
+        let path = std::env::temp_dir().join(format!("rurl-domains-{}.txt", std::process::id()));
+        std::fs::write(&path, "block evil.example\n").unwrap();
+        let config = Config {
+            domain_policy_file: Some(path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let policy = DomainPolicy::from_config(&config).unwrap().unwrap();
+        assert!(policy.check("https://evil.example/").is_err());
+        assert!(policy.check("https://bad.example/").is_ok());
+
+        std::fs::write(&path, "block re:(\n").unwrap();
+        assert!(matches!(
+            DomainPolicy::from_config(&config),
+            Err(DomainPolicyError::Regex { line: 1, .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            DomainPolicy::from_config(&config),
+            Err(DomainPolicyError::Io { .. })
+        ));
+        assert!(
+            DomainPolicy::from_config(&Config::default())
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use axum::http::HeaderMap;
+use reqwest::{StatusCode, header};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::config::{Config, GeneratorKind};
+
+// Hosts never contain it, nor do aliases and namespace names
+const KEY_SEPARATOR: char = '@';
+const REDIRECT_CODES: &[u16] = &[301, 302, 307, 308];
+
+#[derive(Debug, Error)]
+pub enum DomainsError {
+    #[error("Cannot read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid domains file {path}: {source}")]
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+
+    #[error("Invalid domain {domain}: {reason}")]
+    Invalid {
+        domain: String,
+        reason: &'static str,
+    },
+}
+
+fn default_redirect_code() -> u16 {
+    301
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DomainEntry {
+    generator: Option<GeneratorKind>,
+    #[serde(default = "default_redirect_code")]
+    redirect_code: u16,
+    not_found_page: Option<String>,
+    base_url: Option<String>,
+}
+
+/// Branded domain with its own table of links
+#[derive(Debug)]
+pub struct Domain {
+    pub name: String,
+    /// Overrides the configured generator
+    pub generator: Option<GeneratorKind>,
+    /// Status of plain redirects, dynamic ones are always `302`
+    pub redirect_code: StatusCode,
+    /// Contents of the HTML page answered with `404`
+    pub not_found_page: Option<String>,
+    /// Encoded in QR codes, `https://{name}` by default
+    pub base_url: String,
+}
+
+/// Domains from a TOML file, one table per host, e.g. `["go.acme.com"]` with `redirect_code = 302`.
+///
+/// Requests are routed by their `Host` header, hosts not listed use the default table of links.
+#[derive(Debug)]
+pub struct Domains {
+    domains: HashMap<String, Domain>,
+}
+
+impl Domains {
+    pub fn from_config(config: &Config) -> Result<Option<Self>, DomainsError> {
+        let Some(path) = &config.domains_file else {
+            return Ok(None);
+        };
+        let read = |path: &String| {
+            std::fs::read_to_string(path).map_err(|source| DomainsError::Io {
+                path: path.clone(),
+                source,
+            })
+        };
+        let entries: HashMap<String, DomainEntry> =
+            toml::from_str(&read(path)?).map_err(|source| DomainsError::Parse {
+                path: path.clone(),
+                source,
+            })?;
+        let mut domains = HashMap::new();
+        for (name, entry) in entries {
+            let name = name.to_lowercase();
+            let invalid = |reason| DomainsError::Invalid {
+                domain: name.clone(),
+                reason,
+            };
+            if name.is_empty() || name.contains([KEY_SEPARATOR, '/', ':']) {
+                return Err(invalid("Domain must be a host name without a port"));
+            }
+            if !REDIRECT_CODES.contains(&entry.redirect_code) {
+                return Err(invalid("Redirect code must be 301, 302, 307 or 308"));
+            }
+            let domain = Domain {
+                generator: entry.generator,
+                redirect_code: StatusCode::from_u16(entry.redirect_code)
+                    .expect("Redirect codes are valid"),
+                not_found_page: entry.not_found_page.as_ref().map(read).transpose()?,
+                base_url: entry
+                    .base_url
+                    .map(|url| url.trim_end_matches('/').to_string())
+                    .unwrap_or_else(|| format!("https://{name}")),
+                name: name.clone(),
+            };
+            domains.insert(name, domain);
+        }
+        Ok(Some(Domains { domains }))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Domain> {
+        self.domains.get(&name.to_lowercase())
+    }
+
+    /// Domain of the request by its `Host` header, the port is ignored
+    pub fn of_request(&self, headers: &HeaderMap) -> Option<&Domain> {
+        let host = headers.get(header::HOST)?.to_str().ok()?;
+        let name = host.rsplit_once(':').map_or(host, |(name, _)| name);
+        self.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.domains.keys().map(String::as_str)
+    }
+}
+
+/// Key of a link in the storage, links of the default domain keep their plain keys
+pub fn storage_key(domain: Option<&str>, key: String) -> String {
+    match domain {
+        Some(domain) => format!("{domain}{KEY_SEPARATOR}{key}"),
+        None => key,
+    }
+}
+
+/// Key without the domain, which is in the host rather than in the path
+pub fn local_key(key: &str) -> &str {
+    key.split_once(KEY_SEPARATOR).map_or(key, |(_, key)| key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn domains(contents: &str) -> Result<Option<Domains>, DomainsError> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "rurl-domains-{}-{}.toml",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, contents).unwrap();
+        let domains = Domains::from_config(&Config {
+            domains_file: Some(path.to_string_lossy().to_string()),
+            ..Default::default()
+        });
+        std::fs::remove_file(&path).unwrap();
+        domains
+    }
+
+    #[test]
+    fn test_load() {
+        let domains = domains(
+            "[\"Go.Acme.com\"]\ngenerator = \"wordlist\"\nredirect_code = 302\n\n[\"acme.link\"]\n",
+        )
+        .unwrap()
+        .unwrap();
+        let go = domains.get("go.acme.com").unwrap();
+        assert!(go.generator == Some(GeneratorKind::Wordlist));
+        assert!(go.redirect_code == StatusCode::FOUND);
+        assert!(go.base_url == "https://go.acme.com");
+        let link = domains.get("acme.link").unwrap();
+        assert!(link.generator.is_none() && link.redirect_code == StatusCode::MOVED_PERMANENTLY);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "GO.acme.com:8080".parse().unwrap());
+        assert!(domains.of_request(&headers).unwrap().name == "go.acme.com");
+        headers.insert(header::HOST, "sho.rt".parse().unwrap());
+        assert!(domains.of_request(&headers).is_none());
+
+        assert!(Domains::from_config(&Config::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(
+            domains("[\"acme.link\"]\nredirect_code = 200\n"),
+            Err(DomainsError::Invalid { .. })
+        ));
+        assert!(matches!(
+            domains("[\"acme.link:80\"]\n"),
+            Err(DomainsError::Invalid { .. })
+        ));
+        assert!(matches!(
+            domains("[\"acme.link\"]\ncolor = \"red\"\n"),
+            Err(DomainsError::Parse { .. })
+        ));
+        assert!(matches!(
+            domains("[\"acme.link\"]\nnot_found_page = \"/nonexistent/404.html\"\n"),
+            Err(DomainsError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn test_keys() {
+        assert!(storage_key(Some("acme.link"), "docs/wiki".to_string()) == "acme.link@docs/wiki");
+        assert!(storage_key(None, "wiki".to_string()) == "wiki");
+        assert!(local_key("acme.link@docs/wiki") == "docs/wiki");
+        assert!(local_key("wiki") == "wiki");
+    }
+}
@@ -1,16 +1,61 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use axum::{
-    Json,
-    extract::{Path, State},
-    http,
-    response::{IntoResponse, Response},
+    Form, Json,
+    extract::{ConnectInfo, Path, Query, RawQuery, State},
+    http::{self, HeaderMap},
+    response::{Html, IntoResponse, Response},
 };
 use axum_macros::debug_handler;
 use maplit::hashmap;
 use reqwest::{StatusCode, header};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    app::AppState,
+    config::GeneratorKind,
+    domains::{self, Domain},
+    interstitial,
+    link_generator::LinkGenerator,
+    metrics::{
+        GENERATOR_ATTEMPTS_TOTAL, GENERATOR_COLLISIONS_TOTAL, GENERATOR_FALLBACKS_TOTAL,
+        GENERATOR_INVALID_TOTAL, GENERATOR_SUCCESSES_TOTAL, VARIANT_CLICKS_TOTAL,
+    },
+    namespace::{self, Namespace},
+    params::{self, Params},
+    password,
+    qr::{self, QrOptions},
+    schedule::{Destination, Schedule},
+    split::{self, Split},
+    storage::Link,
+    targeting::{self, TargetRule, Visitor},
+    validation::{self, UrlRejection},
+};
 
-use crate::{app::AppState, validation};
+const PASSWORD_FORM: &str = include_str!("templates/password.html");
+const STICKY_VARIANT_MAX_AGE: u64 = 30 * 24 * 3600;
+
+#[derive(Deserialize)]
+pub(crate) struct ShortenRequest {
+    url: Option<String>,
+    alias: Option<String>,
+    password: Option<String>,
+    max_clicks: Option<u64>,
+    #[serde(flatten)]
+    schedule: Schedule,
+    #[serde(default)]
+    targets: Vec<TargetRule>,
+    split: Option<Split>,
+    #[serde(default)]
+    params: Params,
+    param_template: Option<String>,
+    domain: Option<String>,
+}
 
 #[debug_handler]
 pub(crate) async fn status(State(_state): State<Arc<AppState>>) -> StatusCode {
@@ -23,8 +68,13 @@ pub mod api {
     pub mod v1 {
         pub(crate) fn router() -> Router<Arc<AppState>> {
             Router::new()
-                .route("/api/v1/{*link}", axum::routing::get(get))
+                .route("/api/v1/{*link}", axum::routing::get(get).post(unlock))
                 .route("/api/v1/", axum::routing::post(post))
+                .route(
+                    "/t/{namespace}/{*link}",
+                    axum::routing::get(namespaced_get).post(namespaced_unlock),
+                )
+                .route("/t/{namespace}/", axum::routing::post(namespaced_post))
         }
 
         use axum::Router;
@@ -33,82 +83,1092 @@ pub mod api {
         #[debug_handler]
         async fn post(
             State(state): State<Arc<AppState>>,
-            Json(mut params): Json<HashMap<String, String>>,
+            Json(request): Json<ShortenRequest>,
         ) -> Response {
-            log::info!("POST / ({:?})", params);
+            // Password is never logged
+            log::info!(
+                "POST / (url: {:?}, alias: {:?}, max_clicks: {:?})",
+                request.url,
+                request.alias,
+                request.max_clicks
+            );
+            create(&state, None, request).await
+        }
 
-            let mut bad_attempts: Vec<String> = vec![];
-            match params.remove("url") {
+        /// Creation of a link in a namespace, authorized by its API key
+        #[debug_handler]
+        async fn namespaced_post(
+            State(state): State<Arc<AppState>>,
+            Path(name): Path<String>,
+            headers: HeaderMap,
+            Json(request): Json<ShortenRequest>,
+        ) -> Response {
+            log::info!(
+                "POST /t/{name}/ (url: {:?}, alias: {:?}, max_clicks: {:?})",
+                request.url,
+                request.alias,
+                request.max_clicks
+            );
+            let Some(namespace) = state.storage.fetch_namespace(&name).await else {
+                return (StatusCode::NOT_FOUND, "Namespace is not found").into_response();
+            };
+            let api_key = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .unwrap_or_default();
+            if !namespace.verify_key(api_key) {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, "Bearer")],
+                    "Invalid API key",
+                )
+                    .into_response();
+            }
+            create(&state, Some(&namespace), request).await
+        }
+
+        /// Table of links a request refers to, by its domain and namespace
+        #[derive(Clone, Copy)]
+        struct Scope<'a> {
+            domain: Option<&'a Domain>,
+            namespace: Option<&'a str>,
+        }
+
+        impl<'a> Scope<'a> {
+            fn of_request(
+                state: &'a AppState,
+                namespace: Option<&'a str>,
+                headers: &HeaderMap,
+            ) -> Self {
+                Scope {
+                    domain: state
+                        .domains
+                        .as_ref()
+                        .and_then(|domains| domains.of_request(headers)),
+                    namespace,
+                }
+            }
+
+            fn key(&self, short: &str) -> String {
+                domains::storage_key(
+                    self.domain.map(|domain| domain.name.as_str()),
+                    namespace::storage_key(self.namespace, short),
+                )
+            }
+        }
+
+        /// Branded domains answer with their own page when a link is missing
+        fn with_not_found_page(scope: Scope<'_>, response: Response) -> Response {
+            match scope
+                .domain
+                .and_then(|domain| domain.not_found_page.as_ref())
+            {
+                Some(page) if response.status() == StatusCode::NOT_FOUND => {
+                    (StatusCode::NOT_FOUND, Html(page.clone())).into_response()
+                }
+                _ => response,
+            }
+        }
+
+        async fn create(
+            state: &AppState,
+            namespace: Option<&Namespace>,
+            request: ShortenRequest,
+        ) -> Response {
+            let domain = match &request.domain {
+                Some(name) => match state.domains.as_ref().and_then(|domains| domains.get(name)) {
+                    Some(domain) => Some(domain),
+                    None => return (StatusCode::BAD_REQUEST, "Unknown domain").into_response(),
+                },
+                None => None,
+            };
+            let scope = Scope {
+                domain,
+                namespace: namespace.map(|namespace| namespace.name.as_str()),
+            };
+            // Namespace settings take precedence over the domain ones
+            let generator_kind = namespace
+                .and_then(|namespace| namespace.generator)
+                .or(domain.and_then(|domain| domain.generator))
+                .filter(|kind| *kind != state.link_generator.kind());
+            let generator = match generator_kind {
+                Some(kind) => state.generators.get(kind).unwrap_or(&state.link_generator),
+                None => &state.link_generator,
+            };
+            match request.url {
                 Some(url) => {
-                    if !validation::is_valid_url(&url) {
-                        return (http::StatusCode::BAD_REQUEST, "Invalid url").into_response();
-                    }
-                    const MAX_ATTEMPTS: usize = 3;
-
-                    for attempt in 1..=MAX_ATTEMPTS {
-                        let short = state.link_generator.generate(&url, &bad_attempts).await;
-
-                        let short = if let Some(s) = short {
-                            s
-                        } else {
-                            log::warn!(
-                                "Attempt {}/{} failed to generate short link:",
-                                attempt,
-                                MAX_ATTEMPTS
-                            );
-                            continue;
-                        };
-
-                        if !validation::is_valid_short_link(&short) {
-                            log::warn!(
-                                "Attempt {}/{} failed (short link is not valid: {short})",
-                                attempt,
-                                MAX_ATTEMPTS
-                            );
-                            bad_attempts.push(short);
-                            continue;
+                    let url = match prepare_url(state, url).await {
+                        Ok(url) => url,
+                        Err(rejection) => {
+                            return (http::StatusCode::BAD_REQUEST, rejection.to_string())
+                                .into_response();
                         }
-
-                        if state.storage.store(short.clone(), url.clone()).await {
-                            return (
-                                http::StatusCode::OK,
-                                Json(hashmap! {
-                                    "short" => short
-                                }),
-                            )
+                    };
+                    if request.max_clicks == Some(0) {
+                        return (http::StatusCode::BAD_REQUEST, "Invalid max_clicks")
+                            .into_response();
+                    }
+                    let mut schedule = request.schedule;
+                    if let Err(e) = schedule.validate() {
+                        return (http::StatusCode::BAD_REQUEST, e).into_response();
+                    }
+                    for fallback in [&mut schedule.before_url, &mut schedule.after_url] {
+                        if let Some(url) = fallback.take() {
+                            match prepare_url(state, url).await {
+                                Ok(url) => *fallback = Some(url),
+                                Err(rejection) => {
+                                    return (
+                                        http::StatusCode::BAD_REQUEST,
+                                        format!("Invalid fallback url: {rejection}"),
+                                    )
+                                        .into_response();
+                                }
+                            }
+                        }
+                    }
+                    let mut targets = request.targets;
+                    if state.geoip.is_none() && targets.iter().any(|rule| rule.country.is_some()) {
+                        return (
+                            http::StatusCode::BAD_REQUEST,
+                            "Country rules require a GeoIP database",
+                        )
+                            .into_response();
+                    }
+                    for rule in &mut targets {
+                        match prepare_url(state, std::mem::take(&mut rule.url)).await {
+                            Ok(url) => rule.url = url,
+                            Err(rejection) => {
+                                return (
+                                    http::StatusCode::BAD_REQUEST,
+                                    format!("Invalid target url: {rejection}"),
+                                )
+                                    .into_response();
+                            }
+                        }
+                    }
+                    let mut split = request.split;
+                    if let Some(split) = &mut split {
+                        if let Err(e) = split.validate() {
+                            return (http::StatusCode::BAD_REQUEST, e).into_response();
+                        }
+                        for variant in &mut split.variants {
+                            match prepare_url(state, std::mem::take(&mut variant.url)).await {
+                                Ok(url) => variant.url = url,
+                                Err(rejection) => {
+                                    return (
+                                        http::StatusCode::BAD_REQUEST,
+                                        format!("Invalid variant url: {rejection}"),
+                                    )
+                                        .into_response();
+                                }
+                            }
+                        }
+                    }
+                    if let Err(e) = params::validate(&request.params) {
+                        return (http::StatusCode::BAD_REQUEST, e).into_response();
+                    }
+                    if let Some(name) = &request.param_template
+                        && state
+                            .param_templates
+                            .as_ref()
+                            .is_none_or(|templates| templates.get(name).is_none())
+                    {
+                        return (http::StatusCode::BAD_REQUEST, "Unknown parameter template")
+                            .into_response();
+                    }
+                    let password_hash = match request.password {
+                        Some(password)
+                            if password.is_empty()
+                                || password.len() > password::MAX_PASSWORD_LENGTH =>
+                        {
+                            return (http::StatusCode::BAD_REQUEST, "Invalid password")
+                                .into_response();
+                        }
+                        Some(password) => Some(
+                            tokio::task::spawn_blocking(move || password::hash(&password))
+                                .await
+                                .expect("Password hashing panicked"),
+                        ),
+                        None => None,
+                    };
+                    if let Some(alias) = &request.alias {
+                        if !validation::is_valid_short_link(alias) {
+                            return (http::StatusCode::BAD_REQUEST, "Invalid alias")
+                                .into_response();
+                        }
+                        if !state.alias_filter.is_allowed(alias) {
+                            return (http::StatusCode::BAD_REQUEST, "Alias is not allowed")
                                 .into_response();
                         }
+                    }
+                    let mut link = Link {
+                        url: url.clone(),
+                        preview: None,
+                        password_hash,
+                        max_clicks: request.max_clicks,
+                        schedule: (!schedule.is_empty()).then_some(schedule),
+                        targets,
+                        split,
+                        params: request.params,
+                        param_template: request.param_template,
+                        created_at: Some(state.clock.now()),
+                        interstitial: None,
+                    };
+
+                    // Generated links of the same destination are shared, unless they behave differently
+                    let shared = request.alias.is_none() && link.is_plain();
+                    if shared
+                        && let Some(short) = state.storage.short_by_url(&scope.key(&url)).await
+                        && state
+                            .storage
+                            .fetch(&scope.key(&short))
+                            .await
+                            .is_some_and(|existing| existing.url == url && existing.is_plain())
+                    {
+                        return (
+                            http::StatusCode::OK,
+                            Json(hashmap! {
+                                "short" => short
+                            }),
+                        )
+                            .into_response();
+                    }
+
+                    // The outbound fetch is made only for requests which passed all local checks
+                    link.preview = match &state.preview_fetcher {
+                        Some(fetcher) => match fetcher.fetch(&url).await {
+                            Ok(preview) if !preview.is_empty() => Some(preview),
+                            Ok(_) => None,
+                            Err(e) => {
+                                log::warn!("Cannot fetch preview of {url}: {e}");
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+
+                    // Reserved before storing, so concurrent requests never exceed the quota
+                    let reserved = namespace.filter(|namespace| namespace.max_links.is_some());
+                    if let Some(namespace) = reserved
+                        && let Some(max_links) = namespace.max_links
+                        && !state
+                            .storage
+                            .consume_quota(&namespace.name, max_links)
+                            .await
+                    {
+                        log::warn!(
+                            "Namespace {} has used its quota of {max_links} links",
+                            namespace.name
+                        );
+                        return (StatusCode::TOO_MANY_REQUESTS, "Namespace quota is exceeded")
+                            .into_response();
+                    }
+
+                    let response = store_link(
+                        state,
+                        scope,
+                        generator,
+                        generator_kind,
+                        link,
+                        request.alias,
+                        shared,
+                    )
+                    .await;
+                    // Links which were not created give their slot back
+                    if let Some(namespace) = reserved
+                        && !response.status().is_success()
+                    {
+                        state.storage.release_quota(&namespace.name).await;
+                    }
+                    response
+                }
+                _ => http::StatusCode::BAD_REQUEST.into_response(),
+            }
+        }
 
+        /// Stores the link under the alias or a generated short link
+        async fn store_link(
+            state: &AppState,
+            scope: Scope<'_>,
+            generator: &LinkGenerator,
+            generator_kind: Option<GeneratorKind>,
+            link: Link,
+            alias: Option<String>,
+            shared: bool,
+        ) -> Response {
+            let url = link.url.clone();
+            let mut bad_attempts: Vec<String> = vec![];
+            if let Some(alias) = alias {
+                if !state.storage.store(scope.key(&alias), link).await {
+                    return (http::StatusCode::CONFLICT, "Alias is already taken").into_response();
+                }
+                return (
+                    http::StatusCode::OK,
+                    Json(hashmap! {
+                        "short" => alias
+                    }),
+                )
+                    .into_response();
+            }
+
+            // Pooled keys are random, so they are not used when another generator is chosen
+            if let Some(key_pool) = &state.key_pool
+                && generator_kind.is_none()
+                && let Some(short) = key_pool.pop().await
+            {
+                GENERATOR_ATTEMPTS_TOTAL
+                    .with_label_values(&["key_pool"])
+                    .inc();
+                if state.storage.store(scope.key(&short), link.clone()).await {
+                    GENERATOR_SUCCESSES_TOTAL
+                        .with_label_values(&["key_pool"])
+                        .inc();
+                    if shared {
+                        state
+                            .storage
+                            .index_url(scope.key(&url), short.clone())
+                            .await;
+                    }
+                    return (
+                        http::StatusCode::OK,
+                        Json(hashmap! {
+                            "short" => short
+                        }),
+                    )
+                        .into_response();
+                }
+                log::warn!("Pooled short link is already taken: {short}");
+                GENERATOR_COLLISIONS_TOTAL
+                    .with_label_values(&["key_pool"])
+                    .inc();
+                bad_attempts.push(short);
+            }
+
+            const MAX_ATTEMPTS: usize = 3;
+
+            for attempt in 1..=MAX_ATTEMPTS {
+                let candidates = generator
+                    .generate(&url, link.preview.as_ref(), &bad_attempts)
+                    .await;
+
+                if candidates.is_empty() {
+                    log::warn!(
+                        "Attempt {}/{} failed to generate short link:",
+                        attempt,
+                        MAX_ATTEMPTS
+                    );
+                    continue;
+                }
+
+                for candidate in candidates {
+                    let short = candidate.short;
+                    if !validation::is_valid_short_link(&short) {
                         log::warn!(
-                            "Attempt {}/{} failed (not a unique short link: {short})",
+                            "Attempt {}/{} failed (short link is not valid: {short})",
                             attempt,
                             MAX_ATTEMPTS
                         );
+                        GENERATOR_INVALID_TOTAL
+                            .with_label_values(&[candidate.generator, "invalid"])
+                            .inc();
                         bad_attempts.push(short);
+                        continue;
                     }
 
-                    (
-                        http::StatusCode::SERVICE_UNAVAILABLE,
-                        "Cannot generate unique short link",
-                    )
-                        .into_response()
+                    if !state.alias_filter.is_allowed(&short) {
+                        log::warn!(
+                            "Attempt {}/{} failed (short link is denied: {short})",
+                            attempt,
+                            MAX_ATTEMPTS
+                        );
+                        GENERATOR_INVALID_TOTAL
+                            .with_label_values(&[candidate.generator, "denied"])
+                            .inc();
+                        bad_attempts.push(short);
+                        continue;
+                    }
+
+                    if state.storage.store(scope.key(&short), link.clone()).await {
+                        GENERATOR_SUCCESSES_TOTAL
+                            .with_label_values(&[candidate.generator])
+                            .inc();
+                        if shared {
+                            state
+                                .storage
+                                .index_url(scope.key(&url), short.clone())
+                                .await;
+                        }
+                        let configured = generator.label();
+                        if candidate.generator != configured {
+                            GENERATOR_FALLBACKS_TOTAL
+                                .with_label_values(&[configured, candidate.generator])
+                                .inc();
+                        }
+                        return (
+                            http::StatusCode::OK,
+                            Json(hashmap! {
+                                "short" => short
+                            }),
+                        )
+                            .into_response();
+                    }
+
+                    log::warn!(
+                        "Attempt {}/{} failed (not a unique short link: {short})",
+                        attempt,
+                        MAX_ATTEMPTS
+                    );
+                    GENERATOR_COLLISIONS_TOTAL
+                        .with_label_values(&[candidate.generator])
+                        .inc();
+                    bad_attempts.push(short);
                 }
-                _ => http::StatusCode::BAD_REQUEST.into_response(),
             }
+
+            (
+                http::StatusCode::SERVICE_UNAVAILABLE,
+                "Cannot generate unique short link",
+            )
+                .into_response()
         }
 
-        #[debug_handler]
-        async fn get(State(state): State<Arc<AppState>>, Path(path): Path<String>) -> Response {
-            log::info!("GET /{}", path);
-            match state.storage.fetch(&path).await {
-                Some(full) => (
-                    StatusCode::MOVED_PERMANENTLY,
-                    [(header::LOCATION, full)],
-                    "Moved permanently",
+        /// Validates, canonicalizes and unwraps the destination, then applies the domain policy
+        async fn prepare_url(state: &AppState, url: String) -> Result<String, UrlRejection> {
+            state.url_validator.check(&url).await?;
+            let url = state
+                .url_canonicalizer
+                .canonicalize(&url)
+                .ok_or(UrlRejection::Unparsable)?;
+            let unwrapped = state
+                .link_unwrapper
+                .unwrap(url.clone(), &state.storage)
+                .await?;
+            // Expanded third-party links have not been checked yet
+            let url = if unwrapped != url {
+                state.url_validator.check(&unwrapped).await?;
+                state
+                    .url_canonicalizer
+                    .canonicalize(&unwrapped)
+                    .ok_or(UrlRejection::Unparsable)?
+            } else {
+                url
+            };
+            if let Some(policy) = &state.domain_policy {
+                policy.check(&url)?;
+            }
+            Ok(url)
+        }
+
+        /// Stored link, unless its domain got blocked after creation
+        async fn fetch_link(state: &AppState, path: &str) -> Result<Link, Response> {
+            match state.storage.fetch(path).await {
+                Some(link) => match policy_refusal(state, path, &link.url) {
+                    Some(response) => Err(response),
+                    None => Ok(link),
+                },
+                None => Err((StatusCode::NOT_FOUND, "Not found").into_response()),
+            }
+        }
+
+        /// Links created before their domain was blocked stop working too
+        fn policy_refusal(state: &AppState, path: &str, url: &str) -> Option<Response> {
+            let rejection = state.domain_policy.as_ref()?.check(url).err()?;
+            log::warn!("Refusing to redirect /{path}: {rejection}");
+            Some((StatusCode::FORBIDDEN, rejection.to_string()).into_response())
+        }
+
+        /// Every url the link may redirect to, depending on the time and the visitor
+        fn destinations(link: &Link) -> impl Iterator<Item = &str> {
+            let schedule = link.schedule.iter().flat_map(|schedule| {
+                [&schedule.before_url, &schedule.after_url]
+                    .into_iter()
+                    .flatten()
+            });
+            let variants = link.split.iter().flat_map(|split| &split.variants);
+            std::iter::once(&link.url)
+                .chain(link.targets.iter().map(|rule| &rule.url))
+                .chain(variants.map(|variant| &variant.url))
+                .chain(schedule)
+                .map(String::as_str)
+        }
+
+        fn password_form(status: StatusCode, message: &str) -> Response {
+            (
+                status,
+                [(header::CACHE_CONTROL, "no-store")],
+                Html(PASSWORD_FORM.replace("{message}", message)),
+            )
+                .into_response()
+        }
+
+        /// QR code of the public short link, cacheable since it never changes
+        async fn qr(
+            state: &AppState,
+            domain: Option<&Domain>,
+            short: &str,
+            options: &QrOptions,
+            headers: &HeaderMap,
+        ) -> Response {
+            let base_url = domain
+                .map(|domain| &domain.base_url)
+                .or(state.public_base_url.as_ref());
+            let Some(base_url) = base_url else {
+                return (StatusCode::NOT_FOUND, "QR codes require a public base url")
+                    .into_response();
+            };
+            if let Err(response) = fetch_link(state, short).await {
+                return response;
+            }
+            let data = format!("{base_url}{}", namespace::public_path(short));
+            let etag = options.etag(&data);
+            let cached = headers
+                .get(header::IF_NONE_MATCH)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|tags| {
+                    tags.split(',')
+                        .map(str::trim)
+                        .any(|tag| tag == "*" || tag == etag)
+                });
+            if cached {
+                return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+            }
+            match qr::render(&data, options) {
+                Ok(image) => (
+                    StatusCode::OK,
+                    [
+                        (
+                            header::CONTENT_TYPE,
+                            options.format.content_type().to_string(),
+                        ),
+                        (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+                        (header::ETAG, etag),
+                    ],
+                    image,
                 )
                     .into_response(),
-                None => (StatusCode::NOT_FOUND, "Not found").into_response(),
+                Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+            }
+        }
+
+        fn query_value(query: &Option<String>, name: &str) -> Option<String> {
+            query.as_ref().and_then(|query| {
+                url::form_urlencoded::parse(query.as_bytes())
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            })
+        }
+
+        fn has_flag(query: &Option<String>, flag: &str) -> bool {
+            query_value(query, flag).is_some()
+        }
+
+        /// Short link without the `+` preview suffix, and whether a preview is requested
+        fn preview_request(path: String, query: &Option<String>) -> (String, bool) {
+            match path.strip_suffix('+') {
+                Some(short) => (short.to_string(), true),
+                None => (path, has_flag(query, "preview")),
             }
         }
+
+        fn preview_page(
+            state: &AppState,
+            path: &str,
+            link: &Link,
+            warning: Option<&str>,
+        ) -> Response {
+            let url = with_params(state, link, &link.url);
+            // Relative, so it stays under whatever path the proxy serves the link at
+            let short = domains::local_key(path).rsplit('/').next().unwrap_or(path);
+            let token = state.continue_signer.sign(path, state.clock.now());
+            let continue_to = format!("{short}?continue={token}");
+            (
+                StatusCode::OK,
+                [(header::CACHE_CONTROL, "no-store")],
+                Html(interstitial::render(link, &url, &continue_to, warning)),
+            )
+                .into_response()
+        }
+
+        fn warning<'a>(state: &AppState, link: &'a Link) -> Option<&'a str> {
+            link.interstitial.as_deref().or_else(|| {
+                state
+                    .domain_policy
+                    .as_ref()
+                    .is_some_and(|policy| destinations(link).any(|url| policy.warns(url)))
+                    .then_some(interstitial::DOMAIN_WARNING)
+            })
+        }
+
+        #[debug_handler]
+        async fn get(
+            State(state): State<Arc<AppState>>,
+            Path(path): Path<String>,
+            RawQuery(query): RawQuery,
+            ConnectInfo(peer): ConnectInfo<SocketAddr>,
+            headers: HeaderMap,
+            uri: http::Uri,
+        ) -> Response {
+            log::info!("GET /{}", path);
+            let scope = Scope::of_request(&state, None, &headers);
+            let response = serve(&state, scope, path, query, peer, &headers, &uri).await;
+            with_not_found_page(scope, response)
+        }
+
+        #[debug_handler]
+        async fn namespaced_get(
+            State(state): State<Arc<AppState>>,
+            Path((namespace, path)): Path<(String, String)>,
+            RawQuery(query): RawQuery,
+            ConnectInfo(peer): ConnectInfo<SocketAddr>,
+            headers: HeaderMap,
+            uri: http::Uri,
+        ) -> Response {
+            log::info!("GET /t/{namespace}/{path}");
+            let scope = Scope::of_request(&state, Some(&namespace), &headers);
+            let response = serve(&state, scope, path, query, peer, &headers, &uri).await;
+            with_not_found_page(scope, response)
+        }
+
+        /// Redirect, preview or QR code of the link at `path` of the scope
+        async fn serve(
+            state: &AppState,
+            scope: Scope<'_>,
+            path: String,
+            query: Option<String>,
+            peer: SocketAddr,
+            headers: &HeaderMap,
+            uri: &http::Uri,
+        ) -> Response {
+            // Aliases have no slashes, so the suffix cannot be a part of one
+            let (path, qr_code) = match path.strip_suffix("/qr") {
+                Some(short) => (short.to_string(), true),
+                None => (path, false),
+            };
+            let (short, preview) = preview_request(path, &query);
+            // Namespaced links are only served under their namespace
+            if short.contains('/') {
+                return (StatusCode::NOT_FOUND, "Not found").into_response();
+            }
+            let path = scope.key(&short);
+            if qr_code {
+                return match Query::<QrOptions>::try_from_uri(uri) {
+                    Ok(Query(options)) => qr(state, scope.domain, &path, &options, headers).await,
+                    Err(rejection) => rejection.into_response(),
+                };
+            }
+            let link = match fetch_link(state, &path).await {
+                Ok(link) => link,
+                Err(response) => return response,
+            };
+            // Destinations of protected links are not revealed by previews either
+            if link.password_hash.is_some() {
+                return password_form(StatusCode::OK, "Enter the password to continue.");
+            }
+            if preview {
+                return preview_page(state, &path, &link, warning(state, &link));
+            }
+            // Only a continue link of a page shown just now skips the warning
+            let continued = query_value(&query, "continue").is_some_and(|token| {
+                state
+                    .continue_signer
+                    .verify(&path, &token, state.clock.now())
+            });
+            if !continued && let Some(warning) = warning(state, &link) {
+                return preview_page(state, &path, &link, Some(warning));
+            }
+            if link.max_clicks.is_some()
+                || link.schedule.is_some()
+                || !link.targets.is_empty()
+                || link.split.is_some()
+                // Templates may change, so the redirect must not be cached
+                || link.param_template.is_some()
+            {
+                let visitor = visitor(state, headers, peer);
+                return dynamic_redirect(state, &path, link, &visitor, headers, StatusCode::FOUND)
+                    .await;
+            }
+            let status = scope
+                .domain
+                .map_or(StatusCode::MOVED_PERMANENTLY, |domain| domain.redirect_code);
+            (
+                status,
+                [(header::LOCATION, with_params(state, &link, &link.url))],
+                status.canonical_reason().unwrap_or_default(),
+            )
+                .into_response()
+        }
+
+        // Behind a proxy the peer is the proxy itself. The proxy appends the address it sees to
+        // X-Forwarded-For, entries before it come from the client and may be forged
+        fn client_ip(state: &AppState, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+            state
+                .trust_forwarded_for
+                .then(|| headers.get("x-forwarded-for"))
+                .flatten()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .and_then(|ip| ip.trim().parse().ok())
+                .unwrap_or(peer.ip())
+        }
+
+        fn visitor(state: &AppState, headers: &HeaderMap, peer: SocketAddr) -> Visitor {
+            let header = |name| {
+                headers
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+            };
+            let country = state
+                .geoip
+                .as_ref()
+                .and_then(|geoip| geoip.country(client_ip(state, headers, peer)));
+            Visitor::new(
+                header(header::USER_AGENT.as_str()),
+                header(header::ACCEPT_LANGUAGE.as_str()),
+                country,
+            )
+        }
+
+        /// Picks the destination by the activation window and targeting rules, and counts the click atomically, so
+        /// concurrent requests never exceed the limit. The redirect is not cacheable, otherwise
+        /// browsers would skip both.
+        async fn dynamic_redirect(
+            state: &AppState,
+            path: &str,
+            link: Link,
+            visitor: &Visitor,
+            headers: &HeaderMap,
+            status: StatusCode,
+        ) -> Response {
+            let destination = match &link.schedule {
+                Some(schedule) => schedule.destination(state.clock.now()),
+                None => Destination::Main,
+            };
+            let mut variant = None;
+            let url = match destination {
+                Destination::Main => match (targeting::select(&link.targets, visitor), &link.split)
+                {
+                    (Some(url), _) => url,
+                    (None, Some(split)) => {
+                        let remembered = split
+                            .sticky
+                            .then(|| cookie(headers, &split::cookie_name(domains::local_key(path))))
+                            .flatten();
+                        let index = split.pick(remembered);
+                        variant = Some((split.label(index), split.sticky));
+                        &split.variants[index].url
+                    }
+                    (None, None) => &link.url,
+                },
+                // Fallbacks are not counted as clicks
+                Destination::Fallback(url) => {
+                    if let Some(response) = policy_refusal(state, path, url) {
+                        return response;
+                    }
+                    return uncached_redirect(status, &with_params(state, &link, url));
+                }
+                Destination::NotYetActive => {
+                    return (StatusCode::NOT_FOUND, "Link is not active yet").into_response();
+                }
+                Destination::Ended => {
+                    return (StatusCode::GONE, "Link has expired").into_response();
+                }
+            };
+            if let Some(response) = policy_refusal(state, path, url) {
+                return response;
+            }
+            if let Some(max_clicks) = link.max_clicks
+                && !state.storage.register_click(path, max_clicks).await
+            {
+                log::info!("/{path} has reached its limit of {max_clicks} clicks");
+                return (StatusCode::GONE, "Link has expired").into_response();
+            }
+            let mut response = uncached_redirect(status, &with_params(state, &link, url));
+            if let Some((label, sticky)) = variant {
+                // Every link would be a series of its own, so they are only told apart on request
+                let link_label = if state.metrics_link_labels { path } else { "" };
+                VARIANT_CLICKS_TOTAL
+                    .with_label_values(&[link_label, &label])
+                    .inc();
+                if sticky {
+                    // The name holds the short link, and the path differs behind a proxy
+                    let cookie = format!(
+                        "{}={label}; Path=/; Max-Age={STICKY_VARIANT_MAX_AGE}; HttpOnly; SameSite=Lax",
+                        split::cookie_name(domains::local_key(path)),
+                    );
+                    if let Ok(cookie) = http::HeaderValue::from_str(&cookie) {
+                        response.headers_mut().insert(header::SET_COOKIE, cookie);
+                    }
+                }
+            }
+            response
+        }
+
+        /// Adds the link parameters over the template ones, keys present in the url are kept
+        fn with_params(state: &AppState, link: &Link, url: &str) -> String {
+            let mut merged = Params::new();
+            if let Some(name) = &link.param_template {
+                match state
+                    .param_templates
+                    .as_ref()
+                    .and_then(|templates| templates.get(name))
+                {
+                    Some(template) => merged = template,
+                    None => log::warn!("Parameter template {name} is not found"),
+                }
+            }
+            merged.extend(link.params.clone());
+            params::merge(
+                url,
+                merged
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str())),
+            )
+        }
+
+        fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+            headers
+                .get_all(header::COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(';'))
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value)
+        }
+
+        fn uncached_redirect(status: StatusCode, url: &str) -> Response {
+            (
+                status,
+                [(header::LOCATION, url), (header::CACHE_CONTROL, "no-store")],
+                status.canonical_reason().unwrap_or_default(),
+            )
+                .into_response()
+        }
+
+        /// Submission of the password form, redirects once the password matches
+        #[debug_handler]
+        async fn unlock(
+            State(state): State<Arc<AppState>>,
+            Path(path): Path<String>,
+            RawQuery(query): RawQuery,
+            ConnectInfo(peer): ConnectInfo<SocketAddr>,
+            headers: HeaderMap,
+            Form(form): Form<HashMap<String, String>>,
+        ) -> Response {
+            log::info!("POST /{}", path);
+            let scope = Scope::of_request(&state, None, &headers);
+            let response = unlock_link(&state, scope, path, query, peer, &headers, form).await;
+            with_not_found_page(scope, response)
+        }
+
+        #[debug_handler]
+        async fn namespaced_unlock(
+            State(state): State<Arc<AppState>>,
+            Path((namespace, path)): Path<(String, String)>,
+            RawQuery(query): RawQuery,
+            ConnectInfo(peer): ConnectInfo<SocketAddr>,
+            headers: HeaderMap,
+            Form(form): Form<HashMap<String, String>>,
+        ) -> Response {
+            log::info!("POST /t/{namespace}/{path}");
+            let scope = Scope::of_request(&state, Some(&namespace), &headers);
+            let response = unlock_link(&state, scope, path, query, peer, &headers, form).await;
+            with_not_found_page(scope, response)
+        }
+
+        async fn unlock_link(
+            state: &AppState,
+            scope: Scope<'_>,
+            path: String,
+            query: Option<String>,
+            peer: SocketAddr,
+            headers: &HeaderMap,
+            mut form: HashMap<String, String>,
+        ) -> Response {
+            // The password form of a preview is posted to the preview url
+            let (short, _) = preview_request(path, &query);
+            if short.contains('/') {
+                return (StatusCode::NOT_FOUND, "Not found").into_response();
+            }
+            let path = scope.key(&short);
+            let link = match fetch_link(state, &path).await {
+                Ok(link) => link,
+                Err(response) => return response,
+            };
+            if let Some(hash) = link.password_hash.clone() {
+                let client = client_ip(state, headers, peer);
+                if !state.password_attempts.try_attempt(&path, client) {
+                    log::warn!("Too many password attempts for /{path} from {client}");
+                    return password_form(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "Too many attempts, try again later.",
+                    );
+                }
+                let password = form.remove("password").unwrap_or_default();
+                let matches =
+                    tokio::task::spawn_blocking(move || password::verify(&password, &hash))
+                        .await
+                        .expect("Password verification panicked");
+                if !matches {
+                    return password_form(StatusCode::UNAUTHORIZED, "Wrong password.");
+                }
+            }
+            let visitor = visitor(state, headers, peer);
+            dynamic_redirect(state, &path, link, &visitor, headers, StatusCode::SEE_OTHER).await
+        }
+    }
+}
+
+pub mod admin {
+    use super::*;
+
+    pub(crate) fn router() -> Router<Arc<AppState>> {
+        Router::new()
+            .route(
+                "/admin/interstitial/{short}",
+                axum::routing::put(force_interstitial).delete(remove_interstitial),
+            )
+            .route(
+                "/admin/namespaces",
+                axum::routing::get(list_namespaces).post(create_namespace),
+            )
+    }
+
+    use axum::Router;
+
+    #[derive(Deserialize)]
+    struct InterstitialRequest {
+        message: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct NamespaceRequest {
+        name: String,
+        max_links: Option<u64>,
+        generator: Option<GeneratorKind>,
+    }
+
+    /// Rejection of a request without the admin token, the endpoints do not exist when none is configured
+    fn unauthorized(state: &AppState, headers: &HeaderMap) -> Option<Response> {
+        let Some(token) = &state.admin_token else {
+            return Some((StatusCode::NOT_FOUND, "Not found").into_response());
+        };
+        let given = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        // Compared in constant time, so the token cannot be guessed byte by byte
+        let matches: bool = given.as_bytes().ct_eq(token.as_bytes()).into();
+        (!matches).then(|| {
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                "Unauthorized",
+            )
+                .into_response()
+        })
+    }
+
+    async fn set_interstitial(state: &AppState, short: &str, message: Option<String>) -> Response {
+        let Some(mut link) = state.storage.fetch(short).await else {
+            return (StatusCode::NOT_FOUND, "Not found").into_response();
+        };
+        link.interstitial = message;
+        if !state.storage.update(short, link).await {
+            return (StatusCode::NOT_FOUND, "Not found").into_response();
+        }
+        StatusCode::NO_CONTENT.into_response()
+    }
+
+    #[debug_handler]
+    async fn force_interstitial(
+        State(state): State<Arc<AppState>>,
+        Path(short): Path<String>,
+        headers: HeaderMap,
+        Json(request): Json<InterstitialRequest>,
+    ) -> Response {
+        log::info!("PUT /admin/interstitial/{short}");
+        if let Some(rejection) = unauthorized(&state, &headers) {
+            return rejection;
+        }
+        let message = request
+            .message
+            .filter(|message| !message.trim().is_empty())
+            .unwrap_or_else(|| interstitial::DEFAULT_LINK_WARNING.to_string());
+        set_interstitial(&state, &short, Some(message)).await
+    }
+
+    #[debug_handler]
+    async fn remove_interstitial(
+        State(state): State<Arc<AppState>>,
+        Path(short): Path<String>,
+        headers: HeaderMap,
+    ) -> Response {
+        log::info!("DELETE /admin/interstitial/{short}");
+        if let Some(rejection) = unauthorized(&state, &headers) {
+            return rejection;
+        }
+        set_interstitial(&state, &short, None).await
+    }
+
+    #[debug_handler]
+    async fn create_namespace(
+        State(state): State<Arc<AppState>>,
+        headers: HeaderMap,
+        Json(request): Json<NamespaceRequest>,
+    ) -> Response {
+        log::info!("POST /admin/namespaces (name: {:?})", request.name);
+        if let Some(rejection) = unauthorized(&state, &headers) {
+            return rejection;
+        }
+        if !namespace::is_valid_name(&request.name) {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Namespace name must be 2-32 lowercase letters, digits and dashes",
+            )
+                .into_response();
+        }
+        let (namespace, api_key) = Namespace::new(
+            request.name,
+            request.max_links,
+            request.generator,
+            state.clock.now(),
+        );
+        let name = namespace.name.clone();
+        if !state.storage.store_namespace(namespace).await {
+            return (StatusCode::CONFLICT, "Namespace already exists").into_response();
+        }
+        // The key is only shown once, just its hash is stored
+        (
+            StatusCode::CREATED,
+            Json(serde_json::json!({"name": name, "api_key": api_key})),
+        )
+            .into_response()
+    }
+
+    #[debug_handler]
+    async fn list_namespaces(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+        log::info!("GET /admin/namespaces");
+        if let Some(rejection) = unauthorized(&state, &headers) {
+            return rejection;
+        }
+        let namespaces: Vec<_> = state
+            .storage
+            .list_namespaces()
+            .await
+            .into_iter()
+            .map(|namespace| {
+                serde_json::json!({
+                    "name": namespace.name,
+                    "max_links": namespace.max_links,
+                    "generator": namespace.generator,
+                    "created_at": namespace.created_at,
+                })
+            })
+            .collect();
+        Json(namespaces).into_response()
     }
 }
@@ -0,0 +1,175 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use blake2::{Blake2sMac256, digest::Mac};
+use rand::Rng;
+
+use crate::storage::Link;
+
+const PAGE: &str = include_str!("templates/interstitial.html");
+// Seconds a continue link of a shown page stays valid
+const CONTINUE_LIFETIME: u64 = 600;
+pub const DEFAULT_LINK_WARNING: &str = "This link has been flagged by the administrator.";
+pub const DOMAIN_WARNING: &str = "The destination domain has been flagged by the administrator.";
+
+/// Preview page with the destination, creation date and stored page details of a link.
+///
+/// `continue_to` is where the button leads, the warning is shown on top when given. Everything
+/// taken from the link is escaped, titles and descriptions come from third-party pages.
+pub fn render(link: &Link, url: &str, continue_to: &str, warning: Option<&str>) -> String {
+    let warning = warning
+        .map(|warning| format!("<p><strong>Warning:</strong> {}</p>", escape(warning)))
+        .unwrap_or_default();
+    let created = link
+        .created_at
+        .map(|secs| httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs)))
+        .unwrap_or_else(|| "Unknown".to_string());
+    let mut details = String::new();
+    if let Some(preview) = &link.preview {
+        for (term, value) in [
+            ("Title", preview.best_title()),
+            ("Description", preview.best_description()),
+        ] {
+            if let Some(value) = value {
+                details += &format!("<dt>{term}</dt>\n    <dd>{}</dd>\n    ", escape(value));
+            }
+        }
+    }
+    // Escaped values have no braces, so they cannot inject placeholders
+    PAGE.replacen("{warning}", &warning, 1)
+        .replacen("{url}", &escape(url), 1)
+        .replacen("{created}", &created, 1)
+        .replacen("{details}", details.trim_end(), 1)
+        .replacen("{continue}", &escape(continue_to), 1)
+}
+
+/// Signs the continue links of interstitial pages with a key made at startup.
+///
+/// The signature covers the link and an expiry time, so the warning of a flagged link cannot be
+/// skipped by sharing a continue link made in advance.
+pub struct ContinueSigner {
+    key: [u8; 32],
+}
+
+impl ContinueSigner {
+    pub fn random() -> Self {
+        let mut key = [0; 32];
+        rand::rng().fill(&mut key);
+        ContinueSigner { key }
+    }
+
+    /// Value of the `continue` parameter, `{expires}.{signature}`
+    pub fn sign(&self, path: &str, now: u64) -> String {
+        let expires = now + CONTINUE_LIFETIME;
+        let signature: String = self
+            .mac(path, expires)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("{expires}.{signature}")
+    }
+
+    pub fn verify(&self, path: &str, token: &str, now: u64) -> bool {
+        let Some((expires, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(expires) = expires.parse::<u64>() else {
+            return false;
+        };
+        let signature: Option<Vec<u8>> = (0..signature.len())
+            .step_by(2)
+            .map(|i| {
+                signature
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect();
+        // The verifier compares in constant time
+        now < expires
+            && signature
+                .is_some_and(|signature| self.mac(path, expires).verify_slice(&signature).is_ok())
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> Blake2sMac256 {
+        let mut mac =
+            <Blake2sMac256 as Mac>::new_from_slice(&self.key).expect("Key has the size of the MAC");
+        mac.update(format!("{path}\n{expires}").as_bytes());
+        mac
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            '{' => escaped.push_str("&#123;"),
+            '}' => escaped.push_str("&#125;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::PagePreview;
+
+    #[test]
+    fn test_render() {
+        let mut link = Link::new("https://example.com/?a=1&b=<2>".to_string());
+        link.created_at = Some(1_700_000_000);
+        link.preview = Some(PagePreview {
+            title: Some("<script>alert(1)</script>".to_string()),
+            description: Some("{continue}".to_string()),
+            ..Default::default()
+        });
+        let page = render(
+            &link,
+            &link.url,
+            "/api/v1/wiki?continue",
+            Some(DOMAIN_WARNING),
+        );
+        assert!(page.contains("<code>https://example.com/?a=1&amp;b=&lt;2&gt;</code>"));
+        assert!(page.contains("Tue, 14 Nov 2023 22:13:20 GMT"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("<dd>&#123;continue&#125;</dd>"));
+        assert!(page.contains("href=\"/api/v1/wiki?continue\""));
+        assert!(page.contains(DOMAIN_WARNING));
+
+        let page = render(
+            &Link::new("https://example.com/".to_string()),
+            "https://example.com/",
+            "/",
+            None,
+        );
+        assert!(page.contains("Unknown"));
+        assert!(!page.contains("Warning") && !page.contains("Title"));
+    }
+
+    #[test]
+    fn test_continue_signer() {
+        let signer = ContinueSigner::random();
+        let token = signer.sign("wiki", 1_700_000_000);
+        assert!(signer.verify("wiki", &token, 1_700_000_000));
+        assert!(signer.verify("wiki", &token, 1_700_000_000 + CONTINUE_LIFETIME - 1));
+        assert!(!signer.verify("wiki", &token, 1_700_000_000 + CONTINUE_LIFETIME));
+        assert!(!signer.verify("docs", &token, 1_700_000_000));
+        assert!(!ContinueSigner::random().verify("wiki", &token, 1_700_000_000));
+
+        // Later expiry with the same signature
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = format!("{}.{signature}", 1_800_000_000);
+        assert!(!signer.verify("wiki", &forged, 1_700_000_000));
+        for token in ["", "1800000000", "1800000000.", "x.00", "1800000000.zz"] {
+            assert!(!signer.verify("wiki", token, 1_700_000_000));
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::{Mutex, Notify};
+
+use crate::alias_filter::AliasFilter;
+use crate::app::AppState;
+use crate::config::Config;
+use crate::metrics::KEY_POOL_DEPTH;
+use crate::storage::{Storage, redis::RedisSingleConnection};
+
+const REDIS_SET: &str = "rurl:key_pool";
+const REFILL_INTERVAL: Duration = Duration::from_secs(5);
+// Rejected keys are retried, but a broken storage must not spin the refill forever
+const MAX_GENERATION_TRIES_FACTOR: usize = 4;
+
+enum KeyPoolInner {
+    InMemory(Mutex<Vec<String>>),
+    Redis(RedisSingleConnection),
+}
+
+/// Pre-generated random short links, so `post` doesn't have to look for a free one.
+pub struct KeyPool {
+    inner: KeyPoolInner,
+    capacity: usize,
+    low_water: usize,
+    refill_needed: Notify,
+}
+
+impl KeyPool {
+    pub async fn from_config(config: &Config) -> Option<Self> {
+        let capacity = config.key_pool_size.filter(|&size| size > 0)?;
+        let inner = match &config.redis_endpoint {
+            Some(endpoint) => {
+                KeyPoolInner::Redis(RedisSingleConnection::new(endpoint.to_string()).await)
+            }
+            None => KeyPoolInner::InMemory(Mutex::new(Vec::with_capacity(capacity))),
+        };
+        Some(KeyPool {
+            inner,
+            capacity,
+            low_water: config
+                .key_pool_low_water
+                .unwrap_or(capacity / 4)
+                .min(capacity),
+            refill_needed: Notify::new(),
+        })
+    }
+
+    pub async fn pop(&self) -> Option<String> {
+        let (key, len) = match &self.inner {
+            KeyPoolInner::InMemory(keys) => {
+                let mut keys = keys.lock().await;
+                (keys.pop(), keys.len())
+            }
+            KeyPoolInner::Redis(conn) => {
+                let key = conn.pop_from_set(REDIS_SET).await;
+                (key, conn.set_len(REDIS_SET).await)
+            }
+        };
+        KEY_POOL_DEPTH.set(len as i64);
+        if len < self.low_water {
+            self.refill_needed.notify_one();
+        }
+        key
+    }
+
+    async fn len(&self) -> usize {
+        match &self.inner {
+            KeyPoolInner::InMemory(keys) => keys.lock().await.len(),
+            KeyPoolInner::Redis(conn) => conn.set_len(REDIS_SET).await,
+        }
+    }
+
+    /// Tops the pool up to its capacity with keys that are neither stored nor denied
+    pub async fn refill(&self, storage: &Storage, alias_filter: &AliasFilter) {
+        let missing = self.capacity.saturating_sub(self.len().await);
+        let mut keys = Vec::with_capacity(missing);
+        for _ in 0..missing * MAX_GENERATION_TRIES_FACTOR {
+            if keys.len() == missing {
+                break;
+            }
+            let key = crate::link_generator::random::generate();
+            if alias_filter.is_allowed(&key) && storage.fetch(&key).await.is_none() {
+                keys.push(key);
+            }
+        }
+
+        let len = match &self.inner {
+            KeyPoolInner::InMemory(pool) => {
+                let mut pool = pool.lock().await;
+                pool.extend(keys);
+                pool.len()
+            }
+            KeyPoolInner::Redis(conn) => {
+                conn.add_to_set(REDIS_SET, &keys).await;
+                conn.set_len(REDIS_SET).await
+            }
+        };
+        KEY_POOL_DEPTH.set(len as i64);
+        log::info!("Key pool refilled, {len} keys available");
+    }
+}
+
+/// Refills the pool at start, when it drops below the low-water mark and periodically
+pub fn spawn_refill(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let Some(pool) = &state.key_pool else {
+            return;
+        };
+        loop {
+            if pool.len().await < pool.capacity {
+                pool.refill(&state.storage, &state.alias_filter).await;
+            }
+            let _ = tokio::time::timeout(REFILL_INTERVAL, pool.refill_needed.notified()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn pool(size: usize, low_water: usize) -> KeyPool {
+        KeyPool::from_config(&Config {
+            key_pool_size: Some(size),
+            key_pool_low_water: Some(low_water),
+            ..Default::default()
+        })
+        .await
+        .expect("Key pool is enabled")
+    }
+
+    #[tokio::test]
+    async fn test_key_pool() {
+        let config = Config::default();
+        let storage = Storage::from_config(&config).await;
+        let alias_filter = AliasFilter::from_config(&config).unwrap();
+        let pool = pool(10, 5).await;
+        assert!(pool.len().await == 0);
+        assert!(pool.pop().await.is_none());
+
+        pool.refill(&storage, &alias_filter).await;
+        assert!(pool.len().await == 10);
+
+        let mut keys = std::collections::HashSet::new();
+        for _ in 0..5 {
+            let key = pool.pop().await.expect("Pool is not empty");
+            assert!(crate::validation::is_valid_short_link(&key));
+            assert!(alias_filter.is_allowed(&key));
+            keys.insert(key);
+        }
+        assert!(keys.len() == 5);
+        assert!(pool.len().await == 5);
+
+        // Dropping below the low-water mark asks for a refill
+        pool.pop().await;
+        tokio::time::timeout(Duration::from_secs(1), pool.refill_needed.notified())
+            .await
+            .expect("Refill is not requested");
+
+        pool.refill(&storage, &alias_filter).await;
+        assert!(pool.len().await == 10);
+    }
+
+    #[tokio::test]
+    async fn test_key_pool_disabled_by_default() {
+        assert!(KeyPool::from_config(&Config::default()).await.is_none());
+        assert!(
+            KeyPool::from_config(&Config {
+                key_pool_size: Some(0),
+                ..Default::default()
+            })
+            .await
+            .is_none()
+        );
+    }
+}
@@ -1,7 +1,26 @@
+pub mod alias_filter;
 pub mod app;
+pub mod canonical;
+pub mod clock;
 pub mod config;
+pub mod destination_guard;
+pub mod domain_policy;
+pub mod domains;
 pub mod handlers;
+pub mod interstitial;
+pub mod key_pool;
 pub mod link_generator;
+pub mod link_unwrapper;
 pub mod metrics;
+pub mod namespace;
+pub mod params;
+pub mod password;
+pub mod preview;
+pub mod qr;
+pub mod reloading;
+pub mod resources;
+pub mod schedule;
+pub mod split;
 pub mod storage;
+pub mod targeting;
 pub mod validation;
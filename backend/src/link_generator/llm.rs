@@ -0,0 +1,657 @@
+use std::time::Duration;
+
+use axum::http;
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::{Config, LlmProvider};
+use crate::link_generator::prompt::{PromptError, PromptTemplates, PromptValues};
+use crate::preview::PagePreview;
+
+const DEFAULT_OPENAI_BASE_URL: &str = "https://openrouter.ai/api/v1";
+const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434";
+const DEFAULT_MODEL: &str = "meta-llama/llama-4-maverick:free";
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_CANDIDATES: usize = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("Request to LLM api failed: {0}")]
+    Transport(#[source] reqwest::Error),
+
+    #[error("LLM api responded with {status}: {body}")]
+    Status {
+        status: http::StatusCode,
+        body: String,
+    },
+
+    #[error("Cannot decode LLM api response: {0}")]
+    Decode(#[source] reqwest::Error),
+
+    #[error("LLM api returned no choices")]
+    NoChoices,
+
+    #[error("No short link in model output: {0:?}")]
+    NoShortLink(String),
+}
+
+impl LlmError {
+    /// Short reason for metric labels
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LlmError::Transport(e) if e.is_timeout() => "timeout",
+            LlmError::Transport(_) => "transport",
+            LlmError::Status { .. } => "status",
+            LlmError::Decode(_) => "decode",
+            LlmError::NoChoices => "no_choices",
+            LlmError::NoShortLink(_) => "no_short_link",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiRequestBody {
+    model: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaRequestBody {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    options: OllamaOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ModelResponse {
+    Candidates { short_links: Vec<String> },
+    // Models sometimes ignore the requested format and give a single suggestion
+    Single { short_link: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    message: Message,
+}
+
+/// Suggests short links with a chat model behind an OpenAI-compatible or Ollama API.
+pub struct LlmGenerator {
+    client: reqwest::Client,
+    provider: LlmProvider,
+    base_url: String,
+    model: String,
+    temperature: Option<f32>,
+    api_key: Option<String>,
+    max_retries: u32,
+    candidates: usize,
+    structured_output: bool,
+    prompts: PromptTemplates,
+}
+
+impl LlmGenerator {
+    pub fn from_config(config: &Config) -> Result<Self, PromptError> {
+        let base_url = config
+            .llm_base_url
+            .clone()
+            .unwrap_or_else(|| match config.llm_provider {
+                LlmProvider::OpenAi => DEFAULT_OPENAI_BASE_URL.to_string(),
+                LlmProvider::Ollama => DEFAULT_OLLAMA_BASE_URL.to_string(),
+            });
+        let client = reqwest::Client::builder()
+            .connect_timeout(
+                config
+                    .llm_connect_timeout_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+            )
+            .read_timeout(
+                config
+                    .llm_read_timeout_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(DEFAULT_READ_TIMEOUT),
+            )
+            .build()
+            .expect("Cannot build reqwest::client");
+        Ok(LlmGenerator {
+            client,
+            provider: config.llm_provider,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: config
+                .llm_model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            temperature: config.llm_temperature,
+            api_key: config.llm_api_key.clone(),
+            max_retries: config.llm_max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            candidates: config.llm_candidates.unwrap_or(DEFAULT_CANDIDATES).max(1),
+            structured_output: config.llm_structured_output,
+            prompts: PromptTemplates::from_config(config)?,
+        })
+    }
+
+    fn endpoint(&self) -> String {
+        match self.provider {
+            LlmProvider::OpenAi => format!("{}/chat/completions", self.base_url),
+            LlmProvider::Ollama => format!("{}/api/chat", self.base_url),
+        }
+    }
+
+    /// Returns up to `candidates` suggestions, the best one first.
+    pub async fn generate(
+        &self,
+        full_link: &str,
+        preview: Option<&PagePreview>,
+        bad_attempts: &[String],
+    ) -> Result<Vec<String>, LlmError> {
+        let (system, user) = self.prompts.render(&PromptValues {
+            url: full_link,
+            forbidden: bad_attempts,
+            title: preview.and_then(PagePreview::best_title),
+            description: preview.and_then(PagePreview::best_description),
+            count: self.candidates,
+        });
+        let messages = vec![
+            Message {
+                role: "system".to_string(),
+                content: system,
+            },
+            Message {
+                role: "user".to_string(),
+                content: user,
+            },
+        ];
+
+        let content = self.chat(messages).await?;
+        let mut candidates = parse_candidates(&content)?;
+        candidates.truncate(self.candidates);
+        Ok(candidates)
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<String, LlmError> {
+        let schema = self.structured_output.then(candidates_schema);
+        let body = match self.provider {
+            LlmProvider::OpenAi => serde_json::to_value(OpenAiRequestBody {
+                model: self.model.clone(),
+                messages,
+                temperature: self.temperature,
+                response_format: schema.map(|schema| {
+                    serde_json::json!({
+                        "type": "json_schema",
+                        "json_schema": {"name": "short_links", "strict": true, "schema": schema}
+                    })
+                }),
+            }),
+            LlmProvider::Ollama => serde_json::to_value(OllamaRequestBody {
+                model: self.model.clone(),
+                messages,
+                stream: false,
+                options: OllamaOptions {
+                    temperature: self.temperature,
+                },
+                format: schema,
+            }),
+        }
+        .expect("Request body is serializable");
+
+        let response = self.send_with_retries(&body).await?;
+        match self.provider {
+            LlmProvider::OpenAi => response
+                .json::<OpenAiResponse>()
+                .await
+                .map_err(LlmError::Decode)?
+                .choices
+                .into_iter()
+                .next()
+                .map(|choice| choice.message.content)
+                .ok_or(LlmError::NoChoices),
+            LlmProvider::Ollama => Ok(response
+                .json::<OllamaResponse>()
+                .await
+                .map_err(LlmError::Decode)?
+                .message
+                .content),
+        }
+    }
+
+    // Rate limits, server errors and network failures are retried with exponential backoff
+    async fn send_with_retries(
+        &self,
+        body: &serde_json::Value,
+    ) -> Result<reqwest::Response, LlmError> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let mut request = self.client.post(self.endpoint()).json(body);
+            if let Some(api_key) = &self.api_key {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", api_key));
+            }
+
+            let (error, retry_after) = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = response
+                        .headers()
+                        .get(header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse().ok())
+                        .map(Duration::from_secs);
+                    let body = response.text().await.unwrap_or_default();
+                    let error = LlmError::Status { status, body };
+                    if !is_retryable(status) {
+                        return Err(error);
+                    }
+                    (error, retry_after)
+                }
+                Err(e) => (LlmError::Transport(e), None),
+            };
+
+            if attempt >= self.max_retries {
+                return Err(error);
+            }
+            attempt += 1;
+            let delay = retry_after.unwrap_or(backoff).min(MAX_BACKOFF);
+            log::warn!(
+                "LLM api call failed ({error}), retry {attempt}/{} in {delay:?}",
+                self.max_retries
+            );
+            tokio::time::sleep(delay).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+fn is_retryable(status: http::StatusCode) -> bool {
+    status == http::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn candidates_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "short_links": {"type": "array", "items": {"type": "string"}}
+        },
+        "required": ["short_links"],
+        "additionalProperties": false
+    })
+}
+
+// Models like to wrap the answer in markdown fences or to add some prose around,
+// so every balanced `{...}` in the output is tried in order
+fn parse_candidates(content: &str) -> Result<Vec<String>, LlmError> {
+    let mut candidates = match json_objects(content)
+        .find_map(|object| serde_json::from_str::<ModelResponse>(object).ok())
+    {
+        Some(ModelResponse::Candidates { short_links }) => short_links,
+        Some(ModelResponse::Single { short_link }) => vec![short_link],
+        None => vec![],
+    };
+
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|candidate| seen.insert(candidate.clone()));
+    if candidates.is_empty() {
+        return Err(LlmError::NoShortLink(content.to_string()));
+    }
+    Ok(candidates)
+}
+
+fn json_objects(content: &str) -> impl Iterator<Item = &str> {
+    content.match_indices('{').filter_map(|(start, _)| {
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        for (offset, c) in content[start..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(&content[start..=start + offset]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, extract::State, routing::post};
+    use serde_json::{Value, json};
+    use std::sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    #[derive(Clone, Default)]
+    struct Recorded {
+        body: Arc<Mutex<Option<Value>>>,
+        authorization: Arc<Mutex<Option<String>>>,
+    }
+
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    // Serves `response` on `path` and records the last request, returns base url of the server
+    async fn mock_server(path: &str, response: Value) -> (String, Recorded) {
+        let recorded = Recorded::default();
+        let router = Router::new()
+            .route(
+                path,
+                post(
+                    move |State(recorded): State<Recorded>,
+                          headers: http::HeaderMap,
+                          Json(body): Json<Value>| async move {
+                        *recorded.body.lock().unwrap() = Some(body);
+                        *recorded.authorization.lock().unwrap() = headers
+                            .get(header::AUTHORIZATION)
+                            .map(|v| v.to_str().unwrap().to_string());
+                        Json(response)
+                    },
+                ),
+            )
+            .with_state(recorded.clone());
+        (serve(router).await, recorded)
+    }
+
+    fn openai_response(content: &str) -> Value {
+        json!({
+            "id": "gen-1",
+            "choices": [{"message": {"role": "assistant", "content": content}}]
+        })
+    }
+
+    fn config(provider: LlmProvider, base_url: String, api_key: Option<&str>) -> Config {
+        Config {
+            llm_provider: provider,
+            llm_base_url: Some(base_url),
+            llm_model: Some("test-model".to_string()),
+            llm_temperature: Some(0.5),
+            llm_api_key: api_key.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn generator(provider: LlmProvider, base_url: String, api_key: Option<&str>) -> LlmGenerator {
+        LlmGenerator::from_config(&config(provider, base_url, api_key)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_provider() {
+        let (base_url, recorded) = mock_server(
+            "/v1/chat/completions",
+            openai_response("{\"short_links\": [\"example\", \"sample\"]}"),
+        )
+        .await;
+        let generator = generator(LlmProvider::OpenAi, format!("{base_url}/v1/"), Some("key"));
+
+        let short = generator
+            .generate("https://example.com", None, &["bad".to_string()])
+            .await;
+        assert!(short.unwrap() == ["example", "sample"]);
+
+        let body = recorded.body.lock().unwrap().take().expect("No request");
+        assert!(body["model"] == "test-model");
+        assert!(body["messages"][0]["role"] == "system");
+        assert!(body["messages"][1]["role"] == "user");
+        assert!(body["temperature"] == 0.5);
+        assert!(
+            body["messages"][1]["content"]
+                .as_str()
+                .unwrap()
+                .contains("[\"bad\"]")
+        );
+        assert!(*recorded.authorization.lock().unwrap() == Some("Bearer key".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_provider() {
+        let (base_url, recorded) = mock_server(
+            "/api/chat",
+            json!({
+                "model": "test-model",
+                "message": {"role": "assistant", "content": "{\"short_link\": \"local\"}"},
+                "done": true
+            }),
+        )
+        .await;
+        let generator = generator(LlmProvider::Ollama, base_url, None);
+
+        let short = generator.generate("https://example.com", None, &[]).await;
+        assert!(short.unwrap() == ["local"]);
+
+        let body = recorded.body.lock().unwrap().take().expect("No request");
+        assert!(body["stream"] == false);
+        assert!(body["options"]["temperature"] == 0.5);
+        assert!(recorded.authorization.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_provider() {
+        let generator = LlmGenerator::from_config(&Config {
+            llm_max_retries: Some(0),
+            ..config(LlmProvider::OpenAi, "http://127.0.0.1:1".to_string(), None)
+        })
+        .unwrap();
+        assert!(matches!(
+            generator.generate("https://example.com", None, &[]).await,
+            Err(LlmError::Transport(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_empty_choices() {
+        let (base_url, _) = mock_server("/chat/completions", json!({"choices": []})).await;
+        let generator = generator(LlmProvider::OpenAi, base_url, None);
+        assert!(matches!(
+            generator.generate("https://example.com", None, &[]).await,
+            Err(LlmError::NoChoices)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retries_on_rate_limit_and_server_errors() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let router = Router::new()
+            .route(
+                "/chat/completions",
+                post(|State(calls): State<Arc<AtomicUsize>>| async move {
+                    match calls.fetch_add(1, Ordering::SeqCst) {
+                        0 => (http::StatusCode::TOO_MANY_REQUESTS, Json(json!({}))),
+                        1 => (http::StatusCode::BAD_GATEWAY, Json(json!({}))),
+                        _ => (
+                            http::StatusCode::OK,
+                            Json(openai_response("{\"short_links\": [\"third\"]}")),
+                        ),
+                    }
+                }),
+            )
+            .with_state(calls.clone());
+        let base_url = serve(router).await;
+
+        let generator = generator(LlmProvider::OpenAi, base_url.clone(), None);
+        assert!(
+            generator
+                .generate("https://example.com", None, &[])
+                .await
+                .unwrap()
+                == ["third"]
+        );
+        assert!(calls.load(Ordering::SeqCst) == 3);
+
+        calls.store(0, Ordering::SeqCst);
+        let generator = LlmGenerator::from_config(&Config {
+            llm_max_retries: Some(1),
+            ..config(LlmProvider::OpenAi, base_url, None)
+        })
+        .unwrap();
+        assert!(matches!(
+            generator.generate("https://example.com", None, &[]).await,
+            Err(LlmError::Status { status, .. }) if status == http::StatusCode::BAD_GATEWAY
+        ));
+        assert!(calls.load(Ordering::SeqCst) == 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let router = Router::new()
+            .route(
+                "/chat/completions",
+                post(|State(calls): State<Arc<AtomicUsize>>| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    http::StatusCode::UNAUTHORIZED
+                }),
+            )
+            .with_state(calls.clone());
+        let generator = generator(LlmProvider::OpenAi, serve(router).await, None);
+        assert!(matches!(
+            generator.generate("https://example.com", None, &[]).await,
+            Err(LlmError::Status { status, .. }) if status == http::StatusCode::UNAUTHORIZED
+        ));
+        assert!(calls.load(Ordering::SeqCst) == 1);
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let router = Router::new().route(
+            "/chat/completions",
+            post(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Json(openai_response("{\"short_link\": \"late\"}"))
+            }),
+        );
+        let generator = LlmGenerator::from_config(&Config {
+            llm_read_timeout_ms: Some(100),
+            llm_max_retries: Some(0),
+            ..config(LlmProvider::OpenAi, serve(router).await, None)
+        })
+        .unwrap();
+        assert!(matches!(
+            generator.generate("https://example.com", None, &[]).await,
+            Err(LlmError::Transport(e)) if e.is_timeout()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_structured_output() {
+        let (base_url, recorded) = mock_server(
+            "/chat/completions",
+            openai_response("{\"short_links\": [\"a1\", \"b2\", \"a1\", \"c3\", \"d4\"]}"),
+        )
+        .await;
+        let generator = LlmGenerator::from_config(&Config {
+            llm_candidates: Some(3),
+            llm_structured_output: true,
+            ..config(LlmProvider::OpenAi, base_url, None)
+        })
+        .unwrap();
+
+        let preview = PagePreview {
+            title: Some("Example Domain".to_string()),
+            ..Default::default()
+        };
+        let candidates = generator
+            .generate("https://example.com", Some(&preview), &[])
+            .await;
+        assert!(candidates.unwrap() == ["a1", "b2", "c3"]);
+
+        let body = recorded.body.lock().unwrap().take().expect("No request");
+        assert!(body["response_format"]["type"] == "json_schema");
+        assert!(body["response_format"]["json_schema"]["schema"]["required"][0] == "short_links");
+        assert!(
+            body["messages"][1]["content"]
+                .as_str()
+                .unwrap()
+                .contains("Give 3 different suggestions")
+        );
+        assert!(
+            body["messages"][1]["content"]
+                .as_str()
+                .unwrap()
+                .contains("Page title: Example Domain")
+        );
+    }
+
+    #[test]
+    fn test_parse_candidates() {
+        assert!(parse_candidates("{\"short_links\": [\"plain\"]}").unwrap() == ["plain"]);
+        assert!(parse_candidates("{\"short_link\": \"single\"}").unwrap() == ["single"]);
+        assert!(
+            parse_candidates("```json\n{\"short_links\": [\"fenced\", \"code\"]}\n```").unwrap()
+                == ["fenced", "code"]
+        );
+        assert!(
+            parse_candidates("Sure! Here it is: {\"short_links\": [\"chatty\"]} Hope it helps {:}")
+                .unwrap()
+                == ["chatty"]
+        );
+        assert!(
+            parse_candidates("{\"note\": \"braces } inside\", \"short_link\": \"tricky\"}")
+                .unwrap()
+                == ["tricky"]
+        );
+        assert!(
+            parse_candidates("{\"other\": 1} then {\"short_links\": [\"second\"]}").unwrap()
+                == ["second"]
+        );
+        assert!(matches!(
+            parse_candidates("{\"short_links\": []}"),
+            Err(LlmError::NoShortLink(_))
+        ));
+        assert!(matches!(
+            parse_candidates("I cannot help with that"),
+            Err(LlmError::NoShortLink(_))
+        ));
+        assert!(matches!(
+            parse_candidates("{\"short_links\": [\"unterminated\""),
+            Err(LlmError::NoShortLink(_))
+        ));
+    }
+}
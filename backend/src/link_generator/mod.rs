@@ -1,51 +1,229 @@
-use crate::config::Config;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Instant;
 
-mod openrouter;
-mod random;
+use thiserror::Error;
+
+use crate::config::{Config, GeneratorKind};
+use crate::metrics::{
+    GENERATOR_ATTEMPTS_TOTAL, GENERATOR_FAILURES_TOTAL, GENERATOR_UPSTREAM_LATENCY_MS,
+};
+use crate::preview::PagePreview;
+
+mod llm;
+mod prompt;
+pub(crate) mod random;
+mod wordlist;
+
+#[derive(Debug, Error)]
+pub enum GeneratorError {
+    #[error(transparent)]
+    Wordlist(#[from] wordlist::WordlistError),
+
+    #[error(transparent)]
+    Prompt(#[from] prompt::PromptError),
+}
+
+/// Short link suggested by a generator.
+pub struct Candidate {
+    pub short: String,
+    /// Label of the generator which suggested the link, fallbacks have their own
+    pub generator: &'static str,
+}
+
+impl Candidate {
+    fn new(short: String, generator: &'static str) -> Self {
+        Candidate { short, generator }
+    }
+}
 
 pub enum LinkGenerator {
     Random,
 
+    Wordlist(wordlist::Wordlist),
+
     #[allow(dead_code)]
-    OpenrouterLlama(String),
+    Llm(llm::LlmGenerator),
 
     // TODO come up with approach to do this case modular
-    OpenrouterLlamaWithFallback(String),
+    LlmWithFallback(llm::LlmGenerator),
 }
 
 impl LinkGenerator {
-    pub async fn generate(&self, full_link: &str, bad_attempts: &[String]) -> Option<String> {
+    pub fn kind(&self) -> GeneratorKind {
+        match self {
+            LinkGenerator::Random => GeneratorKind::Random,
+            LinkGenerator::Wordlist(_) => GeneratorKind::Wordlist,
+            LinkGenerator::Llm(_) | LinkGenerator::LlmWithFallback(_) => GeneratorKind::Llm,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
         match self {
-            LinkGenerator::Random => Some(crate::link_generator::random::generate()),
-            LinkGenerator::OpenrouterLlama(token) => {
-                crate::link_generator::openrouter::generate(full_link, token, bad_attempts).await
+            LinkGenerator::Random => "random",
+            LinkGenerator::Wordlist(_) => "wordlist",
+            LinkGenerator::Llm(_) | LinkGenerator::LlmWithFallback(_) => "llm",
+        }
+    }
+
+    /// Returns candidates for a short link, the most preferable one first.
+    pub async fn generate(
+        &self,
+        full_link: &str,
+        preview: Option<&PagePreview>,
+        bad_attempts: &[String],
+    ) -> Vec<Candidate> {
+        GENERATOR_ATTEMPTS_TOTAL
+            .with_label_values(&[self.label()])
+            .inc();
+        match self {
+            LinkGenerator::Random => vec![Candidate::new(random::generate(), "random")],
+            LinkGenerator::Wordlist(wordlist) => {
+                vec![Candidate::new(wordlist.generate(), "wordlist")]
+            }
+            LinkGenerator::Llm(llm) => {
+                Self::generate_with_llm(llm, full_link, preview, bad_attempts)
+                    .await
+                    .unwrap_or_default()
             }
-            LinkGenerator::OpenrouterLlamaWithFallback(token) => {
-                if bad_attempts.len() == 2 {
-                    log::info!(
-                        "Too many attempts for AI generator for link {}, fallback random",
-                        full_link
-                    );
-                    return Some(crate::link_generator::random::generate());
-                }
-                crate::link_generator::openrouter::generate(full_link, token, bad_attempts)
+            LinkGenerator::LlmWithFallback(llm) => {
+                let mut candidates = Self::generate_with_llm(llm, full_link, preview, bad_attempts)
                     .await
-                    .or_else(|| {
-                        log::warn!(
-                            "Cannot generate unique short link with openrouter for link {}",
-                            full_link
-                        );
-                        Some(crate::link_generator::random::generate())
-                    })
+                    .unwrap_or_default();
+                // The random link is the last resort if every suggestion is rejected. It is
+                // counted as a random attempt too, so its successes have a denominator
+                GENERATOR_ATTEMPTS_TOTAL
+                    .with_label_values(&["random"])
+                    .inc();
+                candidates.push(Candidate::new(random::generate(), "random"));
+                candidates
             }
         }
     }
+
+    async fn generate_with_llm(
+        llm: &llm::LlmGenerator,
+        full_link: &str,
+        preview: Option<&PagePreview>,
+        bad_attempts: &[String],
+    ) -> Option<Vec<Candidate>> {
+        let start = Instant::now();
+        let result = llm.generate(full_link, preview, bad_attempts).await;
+        let elapsed = start.elapsed();
+        GENERATOR_UPSTREAM_LATENCY_MS
+            .with_label_values(&["llm"])
+            .observe(elapsed.as_millis() as f64);
+
+        match result {
+            Ok(candidates) => {
+                log::info!(
+                    "LLM suggested {:?} for link {} in {:?}",
+                    candidates,
+                    full_link,
+                    elapsed
+                );
+                Some(
+                    candidates
+                        .into_iter()
+                        .map(|short| Candidate::new(short, "llm"))
+                        .collect(),
+                )
+            }
+            Err(e) => {
+                log::warn!(
+                    "Cannot generate short link with LLM for link {} in {:?}: {e}",
+                    full_link,
+                    elapsed
+                );
+                GENERATOR_FAILURES_TOTAL
+                    .with_label_values(&["llm", e.kind()])
+                    .inc();
+                None
+            }
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, GeneratorError> {
+        LinkGenerator::of_kind(configured_kind(config), config)
+    }
+
+    /// Generator of the given kind, whatever kind is configured
+    pub fn of_kind(kind: GeneratorKind, config: &Config) -> Result<Self, GeneratorError> {
+        Ok(match kind {
+            GeneratorKind::Random => LinkGenerator::Random,
+            GeneratorKind::Wordlist => {
+                LinkGenerator::Wordlist(wordlist::Wordlist::from_config(config)?)
+            }
+            GeneratorKind::Llm => {
+                LinkGenerator::LlmWithFallback(llm::LlmGenerator::from_config(config)?)
+            }
+        })
+    }
+}
+
+fn configured_kind(config: &Config) -> GeneratorKind {
+    match (config.generator, &config.llm_api_key) {
+        (Some(kind), _) => kind,
+        // Local models usually don't need any key, so the LLM is used if asked explicitly
+        (None, Some(_)) => GeneratorKind::Llm,
+        (None, None) => GeneratorKind::Random,
+    }
+}
+
+/// Checks the files of generators other than the configured one, which are only built when a
+/// namespace or domain chooses them
+pub fn check_other_generators(config: &Config) -> Vec<GeneratorError> {
+    let configured = configured_kind(config);
+    let mut errors = vec![];
+    if configured != GeneratorKind::Wordlist
+        && (config.wordlist_adjectives.is_some() || config.wordlist_nouns.is_some())
+        && let Err(e) = wordlist::Wordlist::from_config(config)
+    {
+        errors.push(e.into());
+    }
+    if configured != GeneratorKind::Llm
+        && (config.llm_system_prompt_file.is_some() || config.llm_user_prompt_file.is_some())
+        && let Err(e) = prompt::PromptTemplates::from_config(config)
+    {
+        errors.push(e.into());
+    }
+    errors
+}
+
+/// Generators namespaces and domains may choose instead of the configured one.
+///
+/// Each one is built when it is chosen for the first time, so kinds nobody uses load no
+/// wordlists and make no LLM clients.
+pub struct Generators {
+    config: Config,
+    built: HashMap<GeneratorKind, OnceLock<Option<LinkGenerator>>>,
+}
+
+impl Generators {
     pub fn from_config(config: &Config) -> Self {
-        match &config.openrouter_token {
-            Some(token) => LinkGenerator::OpenrouterLlamaWithFallback(token.clone()),
-            None => LinkGenerator::Random,
+        Generators {
+            config: config.clone(),
+            built: [
+                GeneratorKind::Random,
+                GeneratorKind::Wordlist,
+                GeneratorKind::Llm,
+            ]
+            .into_iter()
+            .map(|kind| (kind, OnceLock::new()))
+            .collect(),
         }
     }
+
+    /// `None` if the generator cannot be built, its files are checked at startup though
+    pub fn get(&self, kind: GeneratorKind) -> Option<&LinkGenerator> {
+        self.built[&kind]
+            .get_or_init(|| {
+                LinkGenerator::of_kind(kind, &self.config)
+                    .inspect_err(|e| log::error!("Cannot build {kind:?} generator: {e}"))
+                    .ok()
+            })
+            .as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -59,13 +237,63 @@ mod tests {
         let keys = ["key1", "key2", "key3"];
         let mut short_links = HashSet::<String>::new();
         for key in keys.iter() {
-            short_links.insert(
+            short_links.extend(
                 link_generator
-                    .generate(key, &vec![])
+                    .generate(key, None, &[])
                     .await
-                    .expect("Cannot generate key"),
+                    .into_iter()
+                    .map(|candidate| candidate.short),
             );
         }
         assert!(short_links.len() == keys.len());
     }
+
+    #[tokio::test]
+    async fn test_wordlist_link_generator() {
+        let link_generator = LinkGenerator::Wordlist(wordlist::Wordlist::embedded());
+        let candidates = link_generator
+            .generate("https://example.com", None, &[])
+            .await;
+        assert!(candidates.len() == 1);
+        assert!(candidates[0].generator == "wordlist");
+        assert!(crate::validation::is_valid_short_link(&candidates[0].short));
+    }
+
+    #[test]
+    fn test_generators_built_on_demand() {
+        let generators = Generators::from_config(&Config::default());
+        let is_built = |kind| generators.built[&kind].get().is_some();
+        assert!(!is_built(GeneratorKind::Wordlist) && !is_built(GeneratorKind::Llm));
+        assert!(
+            generators
+                .get(GeneratorKind::Wordlist)
+                .is_some_and(|generator| generator.kind() == GeneratorKind::Wordlist)
+        );
+        assert!(is_built(GeneratorKind::Wordlist) && !is_built(GeneratorKind::Llm));
+    }
+
+    #[tokio::test]
+    async fn test_llm_fallback() {
+        let link_generator = LinkGenerator::LlmWithFallback(
+            llm::LlmGenerator::from_config(&Config {
+                llm_base_url: Some("http://127.0.0.1:1".to_string()),
+                llm_max_retries: Some(0),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let failures = || {
+            GENERATOR_FAILURES_TOTAL
+                .with_label_values(&["llm", "transport"])
+                .get()
+        };
+        let failures_before = failures();
+
+        let candidates = link_generator
+            .generate("https://example.com", None, &[])
+            .await;
+        assert!(candidates.len() == 1);
+        assert!(candidates[0].generator == "random");
+        assert!(failures() > failures_before);
+    }
 }
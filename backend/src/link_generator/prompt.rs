@@ -0,0 +1,288 @@
+use thiserror::Error;
+
+use crate::config::Config;
+
+const DEFAULT_SYSTEM: &str = include_str!("prompts/system.txt");
+const DEFAULT_USER: &str = include_str!("prompts/user.txt");
+const DEFAULT_LOCALE: &str = "en";
+
+const PLACEHOLDERS: &[&str] = &[
+    "url",
+    "forbidden",
+    "title",
+    "description",
+    "locale",
+    "count",
+];
+
+#[derive(Debug, Error)]
+pub enum PromptError {
+    #[error("Cannot read prompt template {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Unknown placeholder {{{placeholder}}} in {template} prompt template")]
+    UnknownPlaceholder {
+        template: &'static str,
+        placeholder: String,
+    },
+
+    #[error("Placeholder {{{placeholder}}} is required in {template} prompt template")]
+    MissingPlaceholder {
+        template: &'static str,
+        placeholder: &'static str,
+    },
+
+    #[error(
+        "Unmatched '{brace}' in {template} prompt template, use '{brace}{brace}' for a literal one"
+    )]
+    UnmatchedBrace { template: &'static str, brace: char },
+}
+
+/// Values substituted into `{placeholder}`s of prompt templates.
+pub struct PromptValues<'a> {
+    pub url: &'a str,
+    pub forbidden: &'a [String],
+    pub title: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub count: usize,
+}
+
+/// System and user messages for the LLM, `{{` and `}}` stand for literal braces.
+pub struct PromptTemplates {
+    system: String,
+    user: String,
+    locale: String,
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+impl PromptTemplates {
+    pub fn new(system: String, user: String, locale: String) -> Result<Self, PromptError> {
+        for (template, text) in [("system", &system), ("user", &user)] {
+            for token in tokenize(template, text)? {
+                if let Token::Placeholder(placeholder) = token
+                    && !PLACEHOLDERS.contains(&placeholder)
+                {
+                    return Err(PromptError::UnknownPlaceholder {
+                        template,
+                        placeholder: placeholder.to_string(),
+                    });
+                }
+            }
+        }
+        let mentions_url = tokenize("user", &user)?
+            .iter()
+            .any(|token| matches!(token, Token::Placeholder("url")));
+        if !mentions_url {
+            return Err(PromptError::MissingPlaceholder {
+                template: "user",
+                placeholder: "url",
+            });
+        }
+
+        Ok(PromptTemplates {
+            system,
+            user,
+            locale,
+        })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, PromptError> {
+        let system = match &config.llm_system_prompt_file {
+            Some(path) => read_file(path)?,
+            None => DEFAULT_SYSTEM.to_string(),
+        };
+        let user = match &config.llm_user_prompt_file {
+            Some(path) => read_file(path)?,
+            None => DEFAULT_USER.to_string(),
+        };
+        let locale = config
+            .llm_locale
+            .clone()
+            .unwrap_or_else(|| DEFAULT_LOCALE.to_string());
+        PromptTemplates::new(system, user, locale)
+    }
+
+    /// Returns system and user messages
+    pub fn render(&self, values: &PromptValues) -> (String, String) {
+        (
+            self.render_one(&self.system, values),
+            self.render_one(&self.user, values),
+        )
+    }
+
+    fn render_one(&self, template: &str, values: &PromptValues) -> String {
+        let tokens = tokenize("", template).expect("Templates are validated on creation");
+        let mut rendered = String::with_capacity(template.len());
+        for token in tokens {
+            match token {
+                Token::Text(text) => rendered.push_str(text),
+                Token::Placeholder("url") => rendered.push_str(values.url),
+                Token::Placeholder("forbidden") => rendered
+                    .push_str(&serde_json::to_string(values.forbidden).unwrap_or("[]".to_string())),
+                Token::Placeholder("title") => rendered.push_str(values.title.unwrap_or_default()),
+                Token::Placeholder("description") => {
+                    rendered.push_str(values.description.unwrap_or_default())
+                }
+                Token::Placeholder("locale") => rendered.push_str(&self.locale),
+                Token::Placeholder("count") => rendered.push_str(&values.count.to_string()),
+                Token::Placeholder(_) => unreachable!("Templates are validated on creation"),
+            }
+        }
+        rendered
+    }
+}
+
+fn read_file(path: &str) -> Result<String, PromptError> {
+    std::fs::read_to_string(path).map_err(|source| PromptError::Io {
+        path: path.to_string(),
+        source,
+    })
+}
+
+fn tokenize<'a>(template: &'static str, text: &'a str) -> Result<Vec<Token<'a>>, PromptError> {
+    let mut tokens = vec![];
+    let mut rest = text;
+    while let Some(brace) = rest.find(['{', '}']) {
+        tokens.push(Token::Text(&rest[..brace]));
+        let after = &rest[brace + 1..];
+        match (&rest[brace..brace + 1], after.chars().next()) {
+            ("{", Some('{')) => {
+                tokens.push(Token::Text("{"));
+                rest = &after[1..];
+            }
+            ("}", Some('}')) => {
+                tokens.push(Token::Text("}"));
+                rest = &after[1..];
+            }
+            ("{", _) => {
+                let end = after
+                    .find('}')
+                    .filter(|&end| {
+                        end > 0
+                            && after[..end]
+                                .chars()
+                                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    })
+                    .ok_or(PromptError::UnmatchedBrace {
+                        template,
+                        brace: '{',
+                    })?;
+                tokens.push(Token::Placeholder(&after[..end]));
+                rest = &after[end + 1..];
+            }
+            _ => {
+                return Err(PromptError::UnmatchedBrace {
+                    template,
+                    brace: '}',
+                });
+            }
+        }
+    }
+    tokens.push(Token::Text(rest));
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values<'a>(forbidden: &'a [String]) -> PromptValues<'a> {
+        PromptValues {
+            url: "https://example.com",
+            forbidden,
+            title: Some("Example"),
+            description: None,
+            count: 3,
+        }
+    }
+
+    #[test]
+    fn test_default_templates() {
+        let templates = PromptTemplates::from_config(&Config::default()).unwrap();
+        let forbidden = ["taken".to_string()];
+        let (system, user) = templates.render(&values(&forbidden));
+        assert!(system.contains("json"));
+        assert!(user.contains("'https://example.com'"));
+        assert!(user.contains("Page title: Example\n"));
+        assert!(user.contains("Page description: \n"));
+        assert!(user.contains("Give 3 different suggestions"));
+        assert!(user.contains("'en' locale"));
+        assert!(user.contains("[\"taken\"]"));
+        assert!(user.contains("{\"short_links\": [\"url\", \"link\"]}"));
+    }
+
+    #[test]
+    fn test_custom_templates() {
+        let templates = PromptTemplates::new(
+            "Reply in lowercase only, locale {locale}".to_string(),
+            "{url} {{literal}} {forbidden}".to_string(),
+            "de".to_string(),
+        )
+        .unwrap();
+        let (system, user) = templates.render(&values(&[]));
+        assert!(system == "Reply in lowercase only, locale de");
+        assert!(user == "https://example.com {literal} []");
+    }
+
+    #[test]
+    fn test_template_validation() {
+        let new = |user: &str| PromptTemplates::new(String::new(), user.to_string(), "en".into());
+        assert!(matches!(
+            new("{url} {page}"),
+            Err(PromptError::UnknownPlaceholder { placeholder, .. }) if placeholder == "page"
+        ));
+        assert!(matches!(
+            new("{title}"),
+            Err(PromptError::MissingPlaceholder {
+                placeholder: "url",
+                ..
+            })
+        ));
+        assert!(matches!(
+            new("{url} {\"json\": 1}"),
+            Err(PromptError::UnmatchedBrace { brace: '{', .. })
+        ));
+        assert!(matches!(
+            new("{url} }"),
+            Err(PromptError::UnmatchedBrace { brace: '}', .. })
+        ));
+        assert!(matches!(
+            new("{url"),
+            Err(PromptError::UnmatchedBrace { .. })
+        ));
+    }
+
+    #[test]
+    fn test_templates_from_files() {
+        let dir = std::env::temp_dir().join(format!("rurl-prompts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let user = dir.join("user.txt");
+        std::fs::write(&user, "Shorten {url}").unwrap();
+
+        let config = Config {
+            llm_user_prompt_file: Some(user.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let (_, rendered) = PromptTemplates::from_config(&config)
+            .unwrap()
+            .render(&values(&[]));
+        assert!(rendered == "Shorten https://example.com");
+
+        let config = Config {
+            llm_system_prompt_file: Some(dir.join("missing.txt").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            PromptTemplates::from_config(&config),
+            Err(PromptError::Io { .. })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use rand::Rng;
+use thiserror::Error;
+
+use crate::config::Config;
+
+const EMBEDDED_ADJECTIVES: &str = include_str!("words/adjectives.txt");
+const EMBEDDED_NOUNS: &str = include_str!("words/nouns.txt");
+
+// `adjective_noun_NN` has to fit into the 16 characters allowed for a short link,
+// two underscores and two digits leave 11 characters for the words themselves
+const MAX_WORDS_LEN: usize = 16 - 5;
+
+#[derive(Debug, Error)]
+pub enum WordlistError {
+    #[error("Cannot read wordlist {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Wordlists do not contain a fitting adjective/noun pair")]
+    Empty,
+}
+
+/// Generates memorable short links like `brave_otter_42` from adjective and noun lists.
+pub struct Wordlist {
+    adjectives: Vec<String>,
+    nouns: Vec<String>,
+}
+
+impl Wordlist {
+    pub fn new(adjectives: &str, nouns: &str) -> Result<Self, WordlistError> {
+        let nouns = parse_words(nouns);
+        let shortest_noun = nouns
+            .iter()
+            .map(String::len)
+            .min()
+            .ok_or(WordlistError::Empty)?;
+
+        // Adjectives that cannot be paired with any noun would never fit into the limit
+        let adjectives: Vec<String> = parse_words(adjectives)
+            .into_iter()
+            .filter(|adj| adj.len() + shortest_noun <= MAX_WORDS_LEN)
+            .collect();
+        if adjectives.is_empty() {
+            return Err(WordlistError::Empty);
+        }
+
+        Ok(Wordlist { adjectives, nouns })
+    }
+
+    #[cfg(test)]
+    pub fn embedded() -> Self {
+        Wordlist::new(EMBEDDED_ADJECTIVES, EMBEDDED_NOUNS).expect("Embedded wordlists are valid")
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, WordlistError> {
+        let adjectives = match &config.wordlist_adjectives {
+            Some(path) => read_file(path)?,
+            None => EMBEDDED_ADJECTIVES.to_string(),
+        };
+        let nouns = match &config.wordlist_nouns {
+            Some(path) => read_file(path)?,
+            None => EMBEDDED_NOUNS.to_string(),
+        };
+        Wordlist::new(&adjectives, &nouns)
+    }
+
+    pub fn generate(&self) -> String {
+        self.generate_with(&mut rand::rng())
+    }
+
+    pub fn generate_with<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        let adjective = &self.adjectives[rng.random_range(0..self.adjectives.len())];
+        let nouns: Vec<&String> = self
+            .nouns
+            .iter()
+            .filter(|noun| adjective.len() + noun.len() <= MAX_WORDS_LEN)
+            .collect();
+        let noun = nouns[rng.random_range(0..nouns.len())];
+        let number: u8 = rng.random_range(10..100);
+        format!("{adjective}_{noun}_{number}")
+    }
+}
+
+fn read_file(path: &str) -> Result<String, WordlistError> {
+    std::fs::read_to_string(path).map_err(|source| WordlistError::Io {
+        path: path.to_string(),
+        source,
+    })
+}
+
+// One word per line, empty lines and `#` comments are skipped.
+// Words that cannot be a part of a short link are dropped.
+fn parse_words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .filter(|word| word.len() < MAX_WORDS_LEN && word.chars().all(|c| c.is_ascii_lowercase()))
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::is_valid_short_link;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_embedded_wordlist() {
+        let wordlist = Wordlist::embedded();
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..1000 {
+            let short = wordlist.generate_with(&mut rng);
+            assert!(is_valid_short_link(&short), "{short} is not valid");
+            assert!(short.split('_').count() == 3);
+        }
+    }
+
+    #[test]
+    fn test_wordlist_is_deterministic() {
+        let wordlist = Wordlist::embedded();
+        let mut first = StdRng::seed_from_u64(7);
+        let mut second = StdRng::seed_from_u64(7);
+        for _ in 0..10 {
+            assert!(wordlist.generate_with(&mut first) == wordlist.generate_with(&mut second));
+        }
+    }
+
+    #[test]
+    fn test_custom_wordlist() {
+        let wordlist = Wordlist::new(
+            "# comment\n\nBrave\nextraordinarily\nbad-word\n",
+            "otter\nhippopotamus\n",
+        )
+        .expect("Cannot build wordlist");
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let short = wordlist.generate_with(&mut rng);
+            assert!(short.starts_with("brave_otter_"), "unexpected {short}");
+        }
+
+        assert!(matches!(
+            Wordlist::new("extraordinarily", "otter"),
+            Err(WordlistError::Empty)
+        ));
+        assert!(matches!(
+            Wordlist::new("brave", ""),
+            Err(WordlistError::Empty)
+        ));
+    }
+}
@@ -0,0 +1,277 @@
+use std::{collections::HashSet, time::Duration};
+
+use reqwest::header;
+use url::Url;
+
+use crate::canonical;
+use crate::config::Config;
+use crate::destination_guard::DestinationGuard;
+use crate::domains::{self, Domains};
+use crate::storage::Storage;
+use crate::validation::UrlRejection;
+
+const MAX_HOPS: usize = 5;
+const EXPAND_TIMEOUT: Duration = Duration::from_secs(2);
+// Paths under which our short links are served
+const ALIAS_PREFIXES: &[&str] = &["/api/v1/", "/s/", "/t/"];
+const KNOWN_SHORTENERS: &[&str] = &[
+    "bit.ly",
+    "bitly.com",
+    "buff.ly",
+    "cutt.ly",
+    "goo.gl",
+    "is.gd",
+    "lnkd.in",
+    "ow.ly",
+    "rb.gy",
+    "rebrand.ly",
+    "shorturl.at",
+    "t.co",
+    "t.ly",
+    "tiny.cc",
+    "tinyurl.com",
+    "v.gd",
+];
+
+/// Replaces destinations which are short links themselves with their targets.
+///
+/// Links to our own public hosts and domains are resolved through the storage, links to known third-party
+/// shorteners are expanded by following their redirects when enabled. Chains longer than a few
+/// hops are treated as loops.
+pub struct LinkUnwrapper {
+    public_hosts: Vec<String>,
+    domains: Vec<String>,
+    shorteners: HashSet<String>,
+    client: Option<reqwest::Client>,
+}
+
+impl LinkUnwrapper {
+    pub fn from_config(config: &Config, domains: Option<&Domains>) -> Self {
+        let client = config.expand_shorteners.then(|| {
+            reqwest::Client::builder()
+                .timeout(EXPAND_TIMEOUT)
+                .redirect(reqwest::redirect::Policy::none())
+                .user_agent(concat!("rurl-shortener/", env!("CARGO_PKG_VERSION")))
+                .dns_resolver(DestinationGuard::for_fetches(config))
+                .build()
+                .expect("Cannot build reqwest::client")
+        });
+        LinkUnwrapper {
+            public_hosts: config
+                .public_hosts
+                .iter()
+                .map(|host| host.to_lowercase())
+                .collect(),
+            domains: domains
+                .map(|domains| domains.names().map(str::to_string).collect())
+                .unwrap_or_default(),
+            shorteners: KNOWN_SHORTENERS
+                .iter()
+                .map(|host| host.to_string())
+                .collect(),
+            client,
+        }
+    }
+
+    pub async fn unwrap(&self, url: String, storage: &Storage) -> Result<String, UrlRejection> {
+        let mut url = url;
+        for _ in 0..MAX_HOPS {
+            let parsed = Url::parse(&url).map_err(|_| UrlRejection::Unparsable)?;
+            if let Some(domain) = self.own_domain(&parsed) {
+                let short = alias_of(&parsed).ok_or(UrlRejection::SelfReference)?;
+                let link = storage
+                    .fetch(&domains::storage_key(domain, short.to_string()))
+                    .await
+                    .ok_or(UrlRejection::SelfReference)?;
+                // Copying the destination would bypass the password, click limit, schedule or
+                // warning, and drop the parameters
+                if !link.is_plain() {
+                    return Err(UrlRejection::ProtectedLink);
+                }
+                url = link.url;
+                continue;
+            }
+
+            let Some(client) = &self.client else {
+                return Ok(url);
+            };
+            if !canonical::host(&parsed).is_some_and(|host| self.shorteners.contains(&host)) {
+                return Ok(url);
+            }
+            match expand(client, &parsed).await {
+                Some(target) => url = target,
+                None => return Ok(url),
+            }
+        }
+        Err(UrlRejection::RedirectLoop)
+    }
+
+    /// Domain of links to our own hosts, `None` inside for the default one
+    fn own_domain(&self, url: &Url) -> Option<Option<&str>> {
+        let host = canonical::host(url)?;
+        if let Some(domain) = self.domains.iter().find(|domain| **domain == host) {
+            return Some(Some(domain));
+        }
+        let with_port = match url.port_or_known_default() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.clone(),
+        };
+        self.public_hosts
+            .iter()
+            .any(|public| *public == host || *public == with_port)
+            .then_some(None)
+    }
+}
+
+fn alias_of(url: &Url) -> Option<&str> {
+    ALIAS_PREFIXES
+        .iter()
+        .find_map(|prefix| url.path().strip_prefix(prefix))
+        .map(|short| short.trim_end_matches('/'))
+        .filter(|short| !short.is_empty())
+}
+
+// A failed expansion leaves the link as is, the shortener may just be slow or down
+async fn expand(client: &reqwest::Client, url: &Url) -> Option<String> {
+    let response = match client.get(url.as_str()).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            log::warn!("Cannot expand {url}: {e}");
+            return None;
+        }
+    };
+    if !response.status().is_redirection() {
+        return None;
+    }
+    let location = response.headers().get(header::LOCATION)?.to_str().ok()?;
+    url.join(location).ok().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Link;
+    use axum::{Router, response::Redirect, routing::get};
+
+    async fn storage_with(links: &[(&str, &str)]) -> Storage {
+        let storage = Storage::from_config(&Config::default()).await;
+        for (short, url) in links {
+            assert!(
+                storage
+                    .store(short.to_string(), Link::new(url.to_string()))
+                    .await
+            );
+        }
+        storage
+    }
+
+    fn unwrapper() -> LinkUnwrapper {
+        LinkUnwrapper::from_config(
+            &Config {
+                public_hosts: vec!["Sho.rt".to_string(), "localhost:4444".to_string()],
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_own_links() {
+        let storage = storage_with(&[
+            ("wiki", "https://en.wikipedia.org/"),
+            ("chain", "https://sho.rt/s/wiki"),
+            ("loop_a", "https://sho.rt/api/v1/loop_b"),
+            ("loop_b", "http://localhost:4444/api/v1/loop_a"),
+        ])
+        .await;
+        let unwrapper = unwrapper();
+        let unwrap = async |url: &str| unwrapper.unwrap(url.to_string(), &storage).await;
+
+        assert!(unwrap("https://example.com/").await.as_deref() == Ok("https://example.com/"));
+        assert!(
+            unwrap("https://sho.rt/api/v1/wiki").await.as_deref()
+                == Ok("https://en.wikipedia.org/")
+        );
+        assert!(
+            unwrap("https://sho.rt/s/chain/").await.as_deref() == Ok("https://en.wikipedia.org/")
+        );
+        // Other port is another service
+        assert!(unwrap("http://localhost:8080/api/v1/wiki").await.is_ok());
+
+        assert!(unwrap("https://sho.rt/api/v1/missing").await == Err(UrlRejection::SelfReference));
+        assert!(unwrap("https://sho.rt/metrics").await == Err(UrlRejection::SelfReference));
+        assert!(unwrap("https://sho.rt/s/loop_a").await == Err(UrlRejection::RedirectLoop));
+        assert!(unwrap("https://sho.rt./s/loop_a").await == Err(UrlRejection::RedirectLoop));
+        assert!(
+            unwrap("http://localhost.:4444/s/missing").await == Err(UrlRejection::SelfReference)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_protected_links() {
+        let storage = storage_with(&[]).await;
+        let mut secret = Link::new("https://example.com/secret".to_string());
+        secret.password_hash = Some("hash".to_string());
+        assert!(storage.store("secret".to_string(), secret).await);
+        let mut once = Link::new("https://example.com/once".to_string());
+        once.max_clicks = Some(1);
+        assert!(storage.store("once".to_string(), once).await);
+        let mut flagged = Link::new("https://example.com/flagged".to_string());
+        flagged.interstitial = Some("Reported as phishing".to_string());
+        assert!(storage.store("flagged".to_string(), flagged).await);
+        let mut tagged = Link::new("https://example.com/tagged".to_string());
+        tagged.param_template = Some("newsletter".to_string());
+        assert!(storage.store("tagged".to_string(), tagged).await);
+
+        let unwrapper = unwrapper();
+        for url in [
+            "https://sho.rt/s/secret",
+            "https://sho.rt/api/v1/once",
+            "https://sho.rt/s/flagged",
+            "https://sho.rt/s/tagged",
+        ] {
+            assert!(
+                unwrapper.unwrap(url.to_string(), &storage).await
+                    == Err(UrlRejection::ProtectedLink)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expand_shorteners() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Router::new()
+            .route("/abc", get(|| async { Redirect::permanent("/hop") }))
+            .route(
+                "/hop",
+                get(|| async { Redirect::temporary("https://sho.rt/s/wiki") }),
+            )
+            .route("/page", get(|| async { "Not a redirect" }));
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let storage = storage_with(&[("wiki", "https://en.wikipedia.org/")]).await;
+        let mut unwrapper = LinkUnwrapper::from_config(
+            &Config {
+                public_hosts: vec!["sho.rt".to_string()],
+                expand_shorteners: true,
+                ..Default::default()
+            },
+            None,
+        );
+        unwrapper.shorteners.insert("127.0.0.1".to_string());
+        let unwrap = async |url: String| unwrapper.unwrap(url, &storage).await;
+
+        assert!(
+            unwrap(format!("http://{addr}/abc")).await.as_deref()
+                == Ok("https://en.wikipedia.org/")
+        );
+        let page = format!("http://{addr}/page");
+        assert!(unwrap(page.clone()).await == Ok(page));
+
+        // Without expansion third-party short links are kept
+        unwrapper.client = None;
+        let short = format!("http://{addr}/abc");
+        assert!(unwrapper.unwrap(short.clone(), &storage).await == Ok(short));
+    }
+}
@@ -1,19 +1,47 @@
+mod alias_filter;
 mod app;
+mod canonical;
+mod clock;
 mod config;
+mod destination_guard;
+mod domain_policy;
+mod domains;
 mod handlers;
+mod interstitial;
+mod key_pool;
 mod link_generator;
+mod link_unwrapper;
 mod metrics;
+mod namespace;
+mod params;
+mod password;
+mod preview;
+mod qr;
+mod reloading;
+mod resources;
+mod schedule;
+mod split;
 mod storage;
+mod targeting;
 mod validation;
 
+use std::sync::Arc;
+
 use app::App;
+use clock::Clock;
 use config::Config;
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let config = Config::from_env();
+    let (config, resources) = match Config::load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(1);
+        }
+    };
 
-    let app = App::from_config(&config).await;
+    let app = App::new(&config, resources, Arc::new(Clock::System)).await;
     app.run().await;
 }
@@ -2,7 +2,8 @@ use axum::response::IntoResponse;
 use axum_macros::debug_handler;
 use lazy_static::lazy_static;
 use prometheus::{
-    HistogramVec, IntCounterVec, TextEncoder, register_histogram_vec, register_int_counter_vec,
+    HistogramVec, IntCounterVec, IntGauge, TextEncoder, register_histogram_vec,
+    register_int_counter_vec, register_int_gauge,
 };
 use std::time::Instant;
 
@@ -20,6 +21,60 @@ lazy_static! {
         vec![1.0, 10.0, 50.0, 200.0, 1000.0, 3000.0, 10000.0]
     )
     .unwrap();
+    pub static ref GENERATOR_ATTEMPTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "generator_attempts_total",
+        "Number of times a generator was asked for short link candidates",
+        &["generator"]
+    )
+    .unwrap();
+    pub static ref GENERATOR_SUCCESSES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "generator_successes_total",
+        "Number of stored short links by the generator which suggested them",
+        &["generator"]
+    )
+    .unwrap();
+    pub static ref GENERATOR_INVALID_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "generator_invalid_total",
+        "Number of rejected short link candidates",
+        &["generator", "reason"]
+    )
+    .unwrap();
+    pub static ref GENERATOR_COLLISIONS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "generator_collisions_total",
+        "Number of short link candidates which are already taken",
+        &["generator"]
+    )
+    .unwrap();
+    pub static ref GENERATOR_FALLBACKS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "generator_fallbacks_total",
+        "Number of short links stored from a fallback instead of the configured generator",
+        &["generator", "fallback"]
+    )
+    .unwrap();
+    pub static ref GENERATOR_FAILURES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "generator_failures_total",
+        "Number of failed calls to upstream generators",
+        &["generator", "reason"]
+    )
+    .unwrap();
+    pub static ref GENERATOR_UPSTREAM_LATENCY_MS: HistogramVec = register_histogram_vec!(
+        "generator_upstream_latency_ms",
+        "Latency of upstream generator calls",
+        &["generator"],
+        vec![10.0, 50.0, 200.0, 500.0, 1000.0, 3000.0, 10000.0, 30000.0]
+    )
+    .unwrap();
+    pub static ref VARIANT_CLICKS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "variant_clicks_total",
+        "Number of redirects to each variant of split links",
+        &["link", "variant"]
+    )
+    .unwrap();
+    pub static ref KEY_POOL_DEPTH: IntGauge = register_int_gauge!(
+        "key_pool_depth",
+        "Number of pre-generated short links available in the key pool"
+    )
+    .unwrap();
 }
 
 #[debug_handler]
@@ -0,0 +1,125 @@
+use blake2::{Blake2b512, Digest};
+use rand::{Rng, distr::Alphanumeric};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+use crate::config::GeneratorKind;
+use crate::domains;
+
+const API_KEY_PREFIX: &str = "rurl_";
+const API_KEY_LENGTH: usize = 40;
+
+/// Team space with its own aliases, API key, quota and generator.
+///
+/// Links of a namespace are stored under `{namespace}/{alias}`, aliases have no slashes, so
+/// namespaces never collide with each other or with links outside of them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Namespace {
+    pub name: String,
+    /// Keys are random, so a fast hash is enough, unlike passwords
+    pub api_key_hash: String,
+    /// Number of links the namespace may create
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_links: Option<u64>,
+    /// Overrides the configured generator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<GeneratorKind>,
+    pub created_at: u64,
+}
+
+impl Namespace {
+    /// Returns the namespace and its API key, which is not stored anywhere
+    pub fn new(
+        name: String,
+        max_links: Option<u64>,
+        generator: Option<GeneratorKind>,
+        now: u64,
+    ) -> (Self, String) {
+        let secret: String = rand::rng()
+            .sample_iter(Alphanumeric)
+            .take(API_KEY_LENGTH)
+            .map(char::from)
+            .collect();
+        let api_key = format!("{API_KEY_PREFIX}{secret}");
+        let namespace = Namespace {
+            name,
+            api_key_hash: hash_api_key(&api_key),
+            max_links,
+            generator,
+            created_at: now,
+        };
+        (namespace, api_key)
+    }
+
+    pub fn verify_key(&self, api_key: &str) -> bool {
+        hash_api_key(api_key)
+            .as_bytes()
+            .ct_eq(self.api_key_hash.as_bytes())
+            .into()
+    }
+}
+
+fn hash_api_key(api_key: &str) -> String {
+    Blake2b512::digest(api_key.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// 2-32 lowercase letters, digits and dashes
+pub fn is_valid_name(name: &str) -> bool {
+    (2..=32).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !name.starts_with('-')
+}
+
+/// Key of a link in the storage
+pub fn storage_key(namespace: Option<&str>, short: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{namespace}/{short}"),
+        None => short.to_string(),
+    }
+}
+
+/// Path the link is served at by the reverse proxy, on its own domain
+pub fn public_path(key: &str) -> String {
+    let key = domains::local_key(key);
+    if key.contains('/') {
+        format!("/t/{key}")
+    } else {
+        format!("/s/{key}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key() {
+        let (namespace, api_key) = Namespace::new("docs-team".to_string(), Some(10), None, 0);
+        assert!(api_key.starts_with("rurl_") && api_key.len() == 45);
+        assert!(!namespace.api_key_hash.contains(&api_key[5..]));
+        assert!(namespace.verify_key(&api_key));
+        assert!(!namespace.verify_key("rurl_guess"));
+
+        let (other, other_key) = Namespace::new("docs-team".to_string(), None, None, 0);
+        assert!(api_key != other_key);
+        assert!(!other.verify_key(&api_key));
+    }
+
+    #[test]
+    fn test_names_and_keys() {
+        assert!(is_valid_name("docs") && is_valid_name("team-42"));
+        for name in ["d", "-docs", "Docs", "docs/x", "docs_x", &"a".repeat(33)] {
+            assert!(!is_valid_name(name), "{name}");
+        }
+        assert!(storage_key(None, "wiki") == "wiki");
+        assert!(storage_key(Some("docs"), "wiki") == "docs/wiki");
+        assert!(public_path("wiki") == "/s/wiki");
+        assert!(public_path("docs/wiki") == "/t/docs/wiki");
+        assert!(public_path("acme.link@docs/wiki") == "/t/docs/wiki");
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, HashMap};
+
+use thiserror::Error;
+use url::Url;
+
+use crate::config::Config;
+use crate::reloading::{FileContents, ReloadingFile};
+const MAX_PARAMS: usize = 32;
+const MAX_PARAM_LENGTH: usize = 256;
+
+pub type Params = BTreeMap<String, String>;
+
+#[derive(Debug, Error)]
+pub enum ParamTemplatesError {
+    #[error("Cannot read parameter templates {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid parameter templates {path}: {source}")]
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+}
+
+struct Templates(HashMap<String, Params>);
+
+impl FileContents for Templates {
+    type Error = ParamTemplatesError;
+
+    const NAME: &'static str = "parameter templates";
+
+    fn read_error(path: &str, source: std::io::Error) -> ParamTemplatesError {
+        ParamTemplatesError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    fn parse(path: &str, contents: &str) -> Result<Self, ParamTemplatesError> {
+        toml::from_str(contents)
+            .map(Templates)
+            .map_err(|source| ParamTemplatesError::Parse {
+                path: path.to_string(),
+                source,
+            })
+    }
+}
+
+/// Named query parameter sets from a TOML file, which is reloaded when it changes.
+///
+/// Each table is a template, e.g. `[newsletter]` with `utm_source = "newsletter"`. Links refer to
+/// templates by name, so the parameters can be changed for all of them at once.
+pub struct ParamTemplates {
+    templates: ReloadingFile<Templates>,
+}
+
+impl ParamTemplates {
+    pub fn from_config(config: &Config) -> Result<Option<Self>, ParamTemplatesError> {
+        let Some(path) = &config.param_templates_file else {
+            return Ok(None);
+        };
+        Ok(Some(ParamTemplates {
+            templates: ReloadingFile::open(path)?,
+        }))
+    }
+
+    pub fn reload_if_changed(&self) {
+        self.templates.reload_if_changed();
+    }
+
+    pub fn get(&self, name: &str) -> Option<Params> {
+        self.templates.read().0.get(name).cloned()
+    }
+}
+
+/// Describes what is wrong with per-link parameters, if anything
+pub fn validate(params: &Params) -> Result<(), &'static str> {
+    if params.len() > MAX_PARAMS {
+        return Err("Too many params");
+    }
+    if params.iter().any(|(key, value)| {
+        key.is_empty() || key.len() > MAX_PARAM_LENGTH || value.len() > MAX_PARAM_LENGTH
+    }) {
+        return Err("Param keys must be 1-256 characters and values up to 256");
+    }
+    Ok(())
+}
+
+/// Appends the parameters missing from the query, keys already in the url are kept as is
+pub fn merge<'a>(url: &str, params: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let existing: Vec<String> = parsed
+        .query_pairs()
+        .map(|(key, _)| key.into_owned())
+        .collect();
+    let missing: Vec<_> = params
+        .into_iter()
+        .filter(|(key, _)| !existing.iter().any(|existing| existing == key))
+        .collect();
+    if missing.is_empty() {
+        return url.to_string();
+    }
+    parsed.query_pairs_mut().extend_pairs(missing);
+    parsed.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let params = [
+            ("utm_source", "newsletter"),
+            ("utm_campaign", "autumn sale"),
+        ];
+        assert!(
+            merge("https://example.com/", params)
+                == "https://example.com/?utm_source=newsletter&utm_campaign=autumn+sale"
+        );
+        assert!(
+            merge("https://example.com/p?id=1&utm_source=blog#top", params)
+                == "https://example.com/p?id=1&utm_source=blog&utm_campaign=autumn+sale#top"
+        );
+        assert!(merge("https://example.com/p?id=1", []) == "https://example.com/p?id=1");
+        assert!(merge("not a url", params) == "not a url");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&Params::from([("utm_source".into(), "x".into())])).is_ok());
+        assert!(validate(&Params::from([(String::new(), "x".into())])).is_err());
+        assert!(validate(&Params::from([("k".into(), "x".repeat(257))])).is_err());
+        let many = (0..33).map(|i| (i.to_string(), String::new())).collect();
+        assert!(validate(&many).is_err());
+    }
+
+    #[test]
+    fn test_from_config() {
+        let path = std::env::temp_dir().join(format!("rurl-params-{}.toml", std::process::id()));
+        std::fs::write(&path, "[newsletter]\nutm_source = \"newsletter\"\n").unwrap();
+        let config = Config {
+            param_templates_file: Some(path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let templates = ParamTemplates::from_config(&config).unwrap().unwrap();
+        let newsletter = templates.get("newsletter").unwrap();
+        assert!(newsletter["utm_source"] == "newsletter");
+        assert!(templates.get("ads").is_none());
+
+        std::fs::write(&path, "[newsletter\n").unwrap();
+        assert!(matches!(
+            ParamTemplates::from_config(&config),
+            Err(ParamTemplatesError::Parse { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            ParamTemplates::from_config(&config),
+            Err(ParamTemplatesError::Io { .. })
+        ));
+        assert!(
+            ParamTemplates::from_config(&Config::default())
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+
+use crate::config::Config;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const ATTEMPTS_WINDOW: Duration = Duration::from_secs(60);
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Salted Argon2 hash in the PHC string format, salt and parameters included
+pub fn hash(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Argon2 with default parameters cannot fail")
+        .to_string()
+}
+
+/// Hashes are compared in constant time by the verifier
+pub fn verify(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+// Short link and client, the global count of a link has no client
+type AttemptKey = (String, Option<IpAddr>);
+
+/// Limits password attempts per client of a short link within a fixed window.
+///
+/// Clients are counted apart, so wrong guesses from one address do not lock the recipients out
+/// of the link. An optional, looser limit counts all clients of a link together.
+pub struct AttemptLimiter {
+    max_attempts: u32,
+    max_global_attempts: Option<u32>,
+    window: Duration,
+    attempts: Mutex<HashMap<AttemptKey, (Instant, u32)>>,
+}
+
+impl AttemptLimiter {
+    pub fn new(max_attempts: u32, max_global_attempts: Option<u32>, window: Duration) -> Self {
+        AttemptLimiter {
+            max_attempts,
+            max_global_attempts,
+            window,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        AttemptLimiter::new(
+            config.password_max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS),
+            config.password_max_global_attempts,
+            ATTEMPTS_WINDOW,
+        )
+    }
+
+    /// Counts an attempt, returns `false` when the limit is already exhausted
+    pub fn try_attempt(&self, short: &str, client: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().expect("Attempts lock is poisoned");
+        // Expired windows are dropped, so the map does not grow with every link ever tried
+        attempts.retain(|_, (started, _)| now.duration_since(*started) < self.window);
+        let count = |attempts: &HashMap<_, (Instant, u32)>, client| {
+            attempts
+                .get(&(short.to_string(), client))
+                .map_or(0, |(_, count)| *count)
+        };
+        if count(&attempts, Some(client)) >= self.max_attempts
+            || self
+                .max_global_attempts
+                .is_some_and(|max| count(&attempts, None) >= max)
+        {
+            return false;
+        }
+        for client in [Some(client), None] {
+            attempts
+                .entry((short.to_string(), client))
+                .or_insert((now, 0))
+                .1 += 1;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify() {
+        let first = hash("s3cret");
+        let second = hash("s3cret");
+        assert!(first.starts_with("$argon2id$"));
+        // Salted, so equal passwords give different hashes
+        assert!(first != second);
+        assert!(verify("s3cret", &first));
+        assert!(verify("s3cret", &second));
+        assert!(!verify("secret", &first));
+        assert!(!verify("s3cret", "not a hash"));
+    }
+
+    #[test]
+    fn test_attempt_limiter() {
+        let (guesser, recipient) = (
+            "203.0.113.7".parse().unwrap(),
+            "198.51.100.1".parse().unwrap(),
+        );
+        let limiter = AttemptLimiter::new(2, None, Duration::from_millis(50));
+        assert!(limiter.try_attempt("docs", guesser));
+        assert!(limiter.try_attempt("docs", guesser));
+        assert!(!limiter.try_attempt("docs", guesser));
+        assert!(limiter.try_attempt("other", guesser));
+        assert!(limiter.try_attempt("docs", recipient));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.try_attempt("docs", guesser));
+    }
+
+    #[test]
+    fn test_global_attempt_limit() {
+        let client = |i: u8| IpAddr::from([203, 0, 113, i]);
+        let limiter = AttemptLimiter::new(2, Some(3), Duration::from_secs(60));
+        assert!(limiter.try_attempt("docs", client(1)));
+        assert!(limiter.try_attempt("docs", client(1)));
+        assert!(!limiter.try_attempt("docs", client(1)));
+        assert!(limiter.try_attempt("docs", client(2)));
+        assert!(!limiter.try_attempt("docs", client(3)));
+        assert!(limiter.try_attempt("other", client(3)));
+    }
+}
@@ -0,0 +1,391 @@
+use std::{sync::Arc, time::Duration};
+
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::destination_guard::DestinationGuard;
+use crate::validation::UrlRejection;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_MAX_BYTES: usize = 256 * 1024;
+const MAX_REDIRECTS: usize = 3;
+const MAX_FIELD_CHARS: usize = 300;
+
+#[derive(Debug, Error)]
+pub enum PreviewError {
+    #[error("Cannot fetch page: {0}")]
+    Transport(#[from] reqwest::Error),
+
+    #[error("Page responded with {0}")]
+    Status(reqwest::StatusCode),
+
+    #[error("Page is not html: {0}")]
+    NotHtml(String),
+
+    #[error("Page is not allowed to be fetched: {0}")]
+    Forbidden(#[from] UrlRejection),
+}
+
+/// What the destination page tells about itself: `<title>`, meta description and Open Graph tags.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PagePreview {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub og_title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub og_description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub og_image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub og_site_name: Option<String>,
+}
+
+impl PagePreview {
+    pub fn is_empty(&self) -> bool {
+        *self == PagePreview::default()
+    }
+
+    /// Open Graph title is usually cleaner than `<title>` with a site name appended
+    pub fn best_title(&self) -> Option<&str> {
+        self.og_title.as_deref().or(self.title.as_deref())
+    }
+
+    pub fn best_description(&self) -> Option<&str> {
+        self.og_description
+            .as_deref()
+            .or(self.description.as_deref())
+    }
+}
+
+/// Downloads the beginning of a page with strict time and size limits.
+pub struct PreviewFetcher {
+    client: reqwest::Client,
+    max_bytes: usize,
+    destination_guard: Arc<DestinationGuard>,
+}
+
+impl PreviewFetcher {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.fetch_preview {
+            return None;
+        }
+        let destination_guard = DestinationGuard::for_fetches(config);
+        let redirect_guard = destination_guard.clone();
+        let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                attempt.error("Too many redirects")
+            } else if redirect_guard.is_forbidden_literal(attempt.url()) {
+                attempt.error("Redirect to a private address")
+            } else {
+                attempt.follow()
+            }
+        });
+        let client = reqwest::Client::builder()
+            .timeout(
+                config
+                    .preview_timeout_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(DEFAULT_TIMEOUT),
+            )
+            .redirect(redirect)
+            .user_agent(concat!("rurl-shortener/", env!("CARGO_PKG_VERSION")))
+            .dns_resolver(destination_guard.clone())
+            .build()
+            .expect("Cannot build reqwest::client");
+        Some(PreviewFetcher {
+            client,
+            max_bytes: config.preview_max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+            destination_guard,
+        })
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<PagePreview, PreviewError> {
+        let parsed = url::Url::parse(url).map_err(|_| UrlRejection::Unparsable)?;
+        self.destination_guard.check(&parsed).await?;
+        let mut response = self
+            .client
+            .get(url)
+            .header(header::ACCEPT, "text/html")
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(PreviewError::Status(response.status()));
+        }
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if !content_type.is_empty() && !content_type.contains("html") {
+            return Err(PreviewError::NotHtml(content_type));
+        }
+
+        // Head of the page is enough, the rest is not even downloaded
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= self.max_bytes {
+                body.truncate(self.max_bytes);
+                break;
+            }
+        }
+        Ok(extract(&String::from_utf8_lossy(&body)))
+    }
+}
+
+pub fn extract(html: &str) -> PagePreview {
+    let lowercase = html.to_ascii_lowercase();
+    let mut preview = PagePreview::default();
+
+    if let Some(start) = lowercase.find("<title")
+        && let Some(content_start) = lowercase[start..].find('>').map(|i| start + i + 1)
+    {
+        let end = lowercase[content_start..]
+            .find("</title")
+            .map_or(html.len(), |i| content_start + i);
+        preview.title = clean(&html[content_start..end]);
+    }
+
+    for (start, _) in lowercase.match_indices("<meta") {
+        let end = lowercase[start..]
+            .find('>')
+            .map_or(html.len(), |i| start + i);
+        let attributes = parse_attributes(&html[start + "<meta".len()..end]);
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let Some(content) = attribute("content").and_then(clean) else {
+            continue;
+        };
+        let key = attribute("property")
+            .or(attribute("name"))
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let field = match key.as_str() {
+            "description" => &mut preview.description,
+            "og:title" => &mut preview.og_title,
+            "og:description" => &mut preview.og_description,
+            "og:image" => &mut preview.og_image,
+            "og:site_name" => &mut preview.og_site_name,
+            _ => continue,
+        };
+        field.get_or_insert(content);
+    }
+
+    preview
+}
+
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = vec![];
+    let mut rest = tag.trim_start().trim_end_matches('/');
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after_eq) = rest.strip_prefix('=') {
+            let after_eq = after_eq.trim_start();
+            let (raw, remaining) = match after_eq.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after_eq[1..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+                    (&after_eq[..end], &after_eq[end..])
+                }
+            };
+            value = raw.to_string();
+            rest = remaining.trim_start();
+        }
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+    }
+    attributes
+}
+
+// Decodes common entities, collapses whitespace and cuts overly long texts
+fn clean(text: &str) -> Option<String> {
+    let decoded = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    let collapsed: String = decoded
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_FIELD_CHARS)
+        .collect();
+    (!collapsed.is_empty()).then_some(collapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, extract::Query, routing::get};
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html><head>
+  <TITLE>
+    Rust &amp; Cargo | Example
+  </TITLE>
+  <meta charset="utf-8">
+  <meta name="description" content="All about   crates">
+  <meta property='og:title' content='Rust and Cargo' />
+  <meta content="https://example.com/logo.png" property="og:image">
+  <meta property="og:description" content="">
+  <meta name=viewport content=width=device-width>
+</head><body>Hello</body></html>"#;
+
+    #[test]
+    fn test_extract() {
+        let preview = extract(PAGE);
+        assert!(preview.title.as_deref() == Some("Rust & Cargo | Example"));
+        assert!(preview.description.as_deref() == Some("All about crates"));
+        assert!(preview.og_title.as_deref() == Some("Rust and Cargo"));
+        assert!(preview.og_image.as_deref() == Some("https://example.com/logo.png"));
+        assert!(preview.og_description.is_none());
+        assert!(preview.best_title() == Some("Rust and Cargo"));
+        assert!(preview.best_description() == Some("All about crates"));
+
+        assert!(extract("<html><body>no head</body></html>").is_empty());
+        assert!(extract("<title>unterminated").title.as_deref() == Some("unterminated"));
+    }
+
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    fn fetcher(max_bytes: usize) -> PreviewFetcher {
+        PreviewFetcher::from_config(&Config {
+            fetch_preview: true,
+            preview_timeout_ms: Some(300),
+            preview_max_bytes: Some(max_bytes),
+            allowed_internal_hosts: vec!["127.0.0.1".to_string()],
+            ..Default::default()
+        })
+        .expect("Preview is enabled")
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let huge = format!("{PAGE}{}", "x".repeat(1024 * 1024));
+        let base_url = serve(
+            Router::new()
+                .route("/page", get(|| async { axum::response::Html(PAGE) }))
+                .route("/huge", get(move || async { axum::response::Html(huge) }))
+                .route("/json", get(|| async { axum::Json("{}") }))
+                .route(
+                    "/slow",
+                    get(|| async {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        axum::response::Html(PAGE)
+                    }),
+                ),
+        )
+        .await;
+
+        let preview = fetcher(1024).fetch(&format!("{base_url}/page")).await;
+        assert!(preview.unwrap().og_title.as_deref() == Some("Rust and Cargo"));
+
+        // Only the head of the page fits the limit, but it is enough
+        let preview = fetcher(PAGE.len()).fetch(&format!("{base_url}/huge")).await;
+        assert!(preview.unwrap().title.is_some());
+
+        assert!(matches!(
+            fetcher(1024).fetch(&format!("{base_url}/json")).await,
+            Err(PreviewError::NotHtml(_))
+        ));
+        assert!(matches!(
+            fetcher(1024).fetch(&format!("{base_url}/missing")).await,
+            Err(PreviewError::Status(_))
+        ));
+        assert!(matches!(
+            fetcher(1024).fetch(&format!("{base_url}/slow")).await,
+            Err(PreviewError::Transport(e)) if e.is_timeout()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_private_destinations() {
+        let base_url = serve(
+            Router::new()
+                .route("/page", get(|| async { axum::response::Html(PAGE) }))
+                .route(
+                    "/redirect",
+                    get(
+                        |Query(params): Query<std::collections::HashMap<String, String>>| async move {
+                            axum::response::Redirect::temporary(&params["to"])
+                        },
+                    ),
+                ),
+        )
+        .await;
+        let port = base_url.rsplit(':').next().unwrap();
+        let fetcher = |allowed_internal_hosts: Vec<String>| {
+            PreviewFetcher::from_config(&Config {
+                fetch_preview: true,
+                preview_timeout_ms: Some(300),
+                allowed_internal_hosts,
+                ..Default::default()
+            })
+            .expect("Preview is enabled")
+        };
+
+        assert!(matches!(
+            fetcher(vec![]).fetch(&format!("{base_url}/page")).await,
+            Err(PreviewError::Forbidden(UrlRejection::PrivateAddress(_)))
+        ));
+        assert!(matches!(
+            fetcher(vec![])
+                .fetch(&format!("http://localhost:{port}/page"))
+                .await,
+            Err(PreviewError::Forbidden(UrlRejection::PrivateAddress(_)))
+        ));
+
+        // Allowed by name, but the redirect leads to a private IP literal
+        let localhost = fetcher(vec!["localhost".to_string()]);
+        assert!(
+            localhost
+                .fetch(&format!("http://localhost:{port}/page"))
+                .await
+                .is_ok()
+        );
+        let redirect = format!("http://localhost:{port}/redirect?to={base_url}/page");
+        assert!(matches!(
+            localhost.fetch(&redirect).await,
+            Err(PreviewError::Transport(e)) if e.is_redirect()
+        ));
+
+        let both = fetcher(vec!["localhost".to_string(), "127.0.0.1".to_string()]);
+        assert!(both.fetch(&redirect).await.is_ok());
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert!(PreviewFetcher::from_config(&Config::default()).is_none());
+    }
+}
@@ -0,0 +1,263 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use qrcode::{Color, EcLevel, QrCode};
+use serde::Deserialize;
+
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 1024;
+const MAX_MARGIN: u32 = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl QrFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            QrFormat::Svg => "image/svg+xml",
+            QrFormat::Png => "image/png",
+        }
+    }
+}
+
+fn default_size() -> u32 {
+    256
+}
+
+fn default_margin() -> u32 {
+    4
+}
+
+fn default_ecc() -> char {
+    'M'
+}
+
+/// Query options of a QR code request
+#[derive(Debug, Hash, Deserialize)]
+pub struct QrOptions {
+    #[serde(default)]
+    pub format: QrFormat,
+    /// Width and height in pixels, rounded down to whole modules
+    #[serde(default = "default_size")]
+    pub size: u32,
+    /// Quiet zone in modules
+    #[serde(default = "default_margin")]
+    pub margin: u32,
+    /// Error correction level, one of `L`, `M`, `Q` and `H`
+    #[serde(default = "default_ecc")]
+    pub ecc: char,
+    /// Hex `RRGGBB` colors, with or without `#`
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        QrOptions {
+            format: QrFormat::default(),
+            size: default_size(),
+            margin: default_margin(),
+            ecc: default_ecc(),
+            fg: None,
+            bg: None,
+        }
+    }
+}
+
+struct Style {
+    size: u32,
+    margin: u32,
+    ecc: EcLevel,
+    fg: [u8; 3],
+    bg: [u8; 3],
+}
+
+impl QrOptions {
+    fn style(&self) -> Result<Style, &'static str> {
+        if !(MIN_SIZE..=MAX_SIZE).contains(&self.size) {
+            return Err("QR code size must be from 64 to 1024 pixels");
+        }
+        if self.margin > MAX_MARGIN {
+            return Err("QR code margin must be up to 16 modules");
+        }
+        let ecc = match self.ecc.to_ascii_uppercase() {
+            'L' => EcLevel::L,
+            'M' => EcLevel::M,
+            'Q' => EcLevel::Q,
+            'H' => EcLevel::H,
+            _ => return Err("Error correction level must be L, M, Q or H"),
+        };
+        let color = |color: &Option<String>, default| match color {
+            Some(color) => parse_color(color).ok_or("Colors must be hex RRGGBB"),
+            None => Ok(default),
+        };
+        Ok(Style {
+            size: self.size,
+            margin: self.margin,
+            ecc,
+            fg: color(&self.fg, [0, 0, 0])?,
+            bg: color(&self.bg, [255, 255, 255])?,
+        })
+    }
+
+    /// Same data and options always render the same image
+    pub fn etag(&self, data: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        self.hash(&mut hasher);
+        format!("\"{:016x}\"", hasher.finish())
+    }
+}
+
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Encodes the data as an SVG or PNG image
+pub fn render(data: &str, options: &QrOptions) -> Result<Vec<u8>, &'static str> {
+    let style = options.style()?;
+    let code = QrCode::with_error_correction_level(data, style.ecc)
+        .map_err(|_| "Link is too long for a QR code")?;
+    let modules = code.width() as u32;
+    let colors = code.to_colors();
+    let total = modules + 2 * style.margin;
+    let is_dark = |x: u32, y: u32| {
+        x >= style.margin
+            && y >= style.margin
+            && x < style.margin + modules
+            && y < style.margin + modules
+            && colors[((y - style.margin) * modules + x - style.margin) as usize] == Color::Dark
+    };
+
+    match options.format {
+        QrFormat::Svg => {
+            let mut path = String::new();
+            for y in 0..total {
+                for x in 0..total {
+                    if is_dark(x, y) {
+                        path += &format!("M{x} {y}h1v1h-1z");
+                    }
+                }
+            }
+            let hex = |[r, g, b]: [u8; 3]| format!("#{r:02x}{g:02x}{b:02x}");
+            Ok(format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {total} {total}\" width=\"{size}\" height=\"{size}\" shape-rendering=\"crispEdges\">\
+                 <rect width=\"100%\" height=\"100%\" fill=\"{bg}\"/><path fill=\"{fg}\" d=\"{path}\"/></svg>",
+                size = style.size,
+                bg = hex(style.bg),
+                fg = hex(style.fg),
+            )
+            .into_bytes())
+        }
+        QrFormat::Png => {
+            // Whole pixels per module keep the code sharp, so the image may be smaller than asked
+            let scale = (style.size / total).max(1);
+            let side = total * scale;
+            let mut pixels = Vec::with_capacity((side * side * 3) as usize);
+            for y in 0..side {
+                for x in 0..side {
+                    let color = if is_dark(x / scale, y / scale) {
+                        style.fg
+                    } else {
+                        style.bg
+                    };
+                    pixels.extend_from_slice(&color);
+                }
+            }
+            let mut image = Vec::new();
+            let mut encoder = png::Encoder::new(&mut image, side, side);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder
+                .write_header()
+                .expect("Writing PNG to memory cannot fail");
+            writer
+                .write_image_data(&pixels)
+                .expect("Writing PNG to memory cannot fail");
+            writer.finish().expect("Writing PNG to memory cannot fail");
+            Ok(image)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svg() {
+        let svg =
+            String::from_utf8(render("https://sho.rt/api/v1/wiki", &QrOptions::default()).unwrap())
+                .unwrap();
+        assert!(svg.starts_with("<svg"));
+        // Version 2 code is 25 modules wide, plus the quiet zone
+        assert!(svg.contains("viewBox=\"0 0 33 33\""));
+        assert!(svg.contains("width=\"256\""));
+        // Top-left finder pattern starts right after the margin
+        assert!(svg.contains("d=\"M4 4h1v1h-1z"));
+        assert!(svg.contains("fill=\"#000000\"") && svg.contains("fill=\"#ffffff\""));
+    }
+
+    #[test]
+    fn test_png() {
+        let options = QrOptions {
+            format: QrFormat::Png,
+            size: 100,
+            margin: 0,
+            fg: Some("#1a2B3c".to_string()),
+            ..Default::default()
+        };
+        let png = render("https://sho.rt/api/v1/wiki", &options).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let decoder = png::Decoder::new(std::io::Cursor::new(png));
+        let reader = decoder.read_info().unwrap();
+        // 25 modules of 4 pixels
+        assert!(reader.info().width == 100 && reader.info().height == 100);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let invalid = |options: QrOptions| render("https://sho.rt/", &options).is_err();
+        assert!(invalid(QrOptions {
+            size: 10,
+            ..Default::default()
+        }));
+        assert!(invalid(QrOptions {
+            margin: 100,
+            ..Default::default()
+        }));
+        assert!(invalid(QrOptions {
+            ecc: 'X',
+            ..Default::default()
+        }));
+        assert!(invalid(QrOptions {
+            bg: Some("white".to_string()),
+            ..Default::default()
+        }));
+        assert!(!invalid(QrOptions {
+            ecc: 'h',
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_etag() {
+        let options = QrOptions::default();
+        assert!(options.etag("https://sho.rt/a") == options.etag("https://sho.rt/a"));
+        assert!(options.etag("https://sho.rt/a") != options.etag("https://sho.rt/b"));
+        let png = QrOptions {
+            format: QrFormat::Png,
+            ..Default::default()
+        };
+        assert!(options.etag("https://sho.rt/a") != png.etag("https://sho.rt/a"));
+    }
+}
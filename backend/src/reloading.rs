@@ -0,0 +1,160 @@
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard},
+    time::{Duration, SystemTime},
+};
+
+use crate::app::AppState;
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Contents of a file which may be edited while the service runs.
+pub trait FileContents: Sized {
+    type Error: Display;
+
+    /// What the file holds, for logs
+    const NAME: &'static str;
+
+    fn read_error(path: &str, source: std::io::Error) -> Self::Error;
+
+    fn parse(path: &str, contents: &str) -> Result<Self, Self::Error>;
+}
+
+/// File contents which are parsed again when the modification time of the file changes.
+pub struct ReloadingFile<T> {
+    path: String,
+    contents: RwLock<T>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl<T: FileContents> ReloadingFile<T> {
+    pub fn open(path: &str) -> Result<Self, T::Error> {
+        let modified = modified(path).map_err(|e| T::read_error(path, e))?;
+        Ok(ReloadingFile {
+            path: path.to_string(),
+            contents: RwLock::new(read(path)?),
+            modified: Mutex::new(Some(modified)),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn with_contents(contents: T) -> Self {
+        ReloadingFile {
+            path: String::new(),
+            contents: RwLock::new(contents),
+            modified: Mutex::new(None),
+        }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.contents
+            .read()
+            .expect("Reloading file lock is poisoned")
+    }
+
+    /// A broken file is reported and the previous contents stay in effect
+    pub fn reload_if_changed(&self) {
+        let modified = modified(&self.path);
+        let mut known = self
+            .modified
+            .lock()
+            .expect("Reloading file lock is poisoned");
+        if modified
+            .as_ref()
+            .is_ok_and(|modified| Some(*modified) == *known)
+        {
+            return;
+        }
+        let result = modified
+            .map_err(|e| T::read_error(&self.path, e))
+            .and_then(|modified| Ok((modified, read(&self.path)?)));
+        match result {
+            Ok((modified, contents)) => {
+                *self
+                    .contents
+                    .write()
+                    .expect("Reloading file lock is poisoned") = contents;
+                *known = Some(modified);
+                log::info!("Reloaded {} {}", T::NAME, self.path);
+            }
+            Err(e) => log::error!("{e}, keeping the previous {}", T::NAME),
+        }
+    }
+}
+
+fn modified(path: &str) -> std::io::Result<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified())
+}
+
+fn read<T: FileContents>(path: &str) -> Result<T, T::Error> {
+    let contents = std::fs::read_to_string(path).map_err(|e| T::read_error(path, e))?;
+    T::parse(path, &contents)
+}
+
+/// Calls `reload` every few seconds, it is expected to check the reloading files of the state
+pub fn spawn_reload(state: Arc<AppState>, reload: fn(&AppState)) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+            reload(&state);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Words(Vec<String>);
+
+    impl FileContents for Words {
+        type Error = String;
+
+        const NAME: &'static str = "words";
+
+        fn read_error(path: &str, source: std::io::Error) -> String {
+            format!("Cannot read {path}: {source}")
+        }
+
+        fn parse(path: &str, contents: &str) -> Result<Self, String> {
+            if contents.contains('!') {
+                return Err(format!("Invalid words {path}"));
+            }
+            Ok(Words(
+                contents.split_whitespace().map(str::to_string).collect(),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_reload() {
+        let path = std::env::temp_dir().join(format!("rurl-reloading-{}.txt", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        std::fs::write(&path, "one two\n").unwrap();
+        let file = ReloadingFile::<Words>::open(&path).unwrap();
+        assert!(file.read().0 == ["one", "two"]);
+
+        // Unchanged file is not read again
+        *file.contents.write().unwrap() = Words(vec![]);
+        file.reload_if_changed();
+        assert!(file.read().0.is_empty());
+
+        // Broken file keeps the previous contents
+        std::fs::write(&path, "three!\n").unwrap();
+        *file.modified.lock().unwrap() = None;
+        file.reload_if_changed();
+        assert!(file.read().0.is_empty());
+
+        std::fs::write(&path, "three\n").unwrap();
+        *file.modified.lock().unwrap() = None;
+        file.reload_if_changed();
+        assert!(file.read().0 == ["three"]);
+
+        // Removed file keeps them too
+        std::fs::remove_file(&path).unwrap();
+        file.reload_if_changed();
+        assert!(file.read().0 == ["three"]);
+        assert!(ReloadingFile::<Words>::open(&path).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt::Display;
+
+use crate::alias_filter::AliasFilter;
+use crate::config::Config;
+use crate::domain_policy::DomainPolicy;
+use crate::domains::Domains;
+use crate::link_generator::{self, LinkGenerator};
+use crate::params::ParamTemplates;
+use crate::targeting::GeoIp;
+
+/// Everything the settings point to on disk, loaded with the settings themselves, so missing or
+/// broken files are reported together with invalid settings.
+pub struct Resources {
+    pub domains: Option<Domains>,
+    pub domain_policy: Option<DomainPolicy>,
+    pub param_templates: Option<ParamTemplates>,
+    pub alias_filter: AliasFilter,
+    pub geoip: Option<GeoIp>,
+    pub link_generator: LinkGenerator,
+}
+
+impl Resources {
+    /// Every failed load is reported, not only the first one
+    pub fn load(config: &Config) -> Result<Self, Vec<String>> {
+        let mut errors = vec![];
+        let domains = collect(&mut errors, Domains::from_config(config));
+        let domain_policy = collect(&mut errors, DomainPolicy::from_config(config));
+        let param_templates = collect(&mut errors, ParamTemplates::from_config(config));
+        let alias_filter = collect(
+            &mut errors,
+            AliasFilter::from_config(config).map_err(|e| {
+                format!(
+                    "Cannot read denylist {}: {e}",
+                    config.denylist_file.as_deref().unwrap_or_default()
+                )
+            }),
+        );
+        let geoip = collect(
+            &mut errors,
+            GeoIp::from_config(config).map_err(|e| {
+                format!(
+                    "Cannot open GeoIP database {}: {e}",
+                    config.geoip_database.as_deref().unwrap_or_default()
+                )
+            }),
+        );
+        let link_generator = collect(&mut errors, LinkGenerator::from_config(config));
+        errors.extend(
+            link_generator::check_other_generators(config)
+                .iter()
+                .map(ToString::to_string),
+        );
+        match (
+            domains,
+            domain_policy,
+            param_templates,
+            alias_filter,
+            geoip,
+            link_generator,
+        ) {
+            (
+                Some(domains),
+                Some(domain_policy),
+                Some(param_templates),
+                Some(alias_filter),
+                Some(geoip),
+                Some(link_generator),
+            ) if errors.is_empty() => Ok(Resources {
+                domains,
+                domain_policy,
+                param_templates,
+                alias_filter,
+                geoip,
+                link_generator,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+fn collect<T, E: Display>(errors: &mut Vec<String>, result: Result<T, E>) -> Option<T> {
+    result.inspect_err(|e| errors.push(e.to_string())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_errors_reported() {
+        assert!(Resources::load(&Config::default()).is_ok());
+
+        let Err(errors) = Resources::load(&Config {
+            domains_file: Some("/nonexistent/domains.toml".to_string()),
+            denylist_file: Some("/nonexistent/denylist.txt".to_string()),
+            geoip_database: Some("/nonexistent/country.mmdb".to_string()),
+            wordlist_nouns: Some("/nonexistent/nouns.txt".to_string()),
+            llm_user_prompt_file: Some("/nonexistent/user.txt".to_string()),
+            ..Default::default()
+        }) else {
+            panic!("Missing files must be reported");
+        };
+        assert!(errors.len() == 5, "{errors:?}");
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.contains("/nonexistent/denylist.txt"))
+        );
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.contains("/nonexistent/country.mmdb"))
+        );
+    }
+}
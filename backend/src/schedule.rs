@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+/// Activation window of a link with optional destinations outside of it.
+///
+/// Bounds are Unix timestamps in seconds, `not_before` is inclusive and `not_after` is exclusive.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<u64>,
+    /// Teaser shown until the link becomes active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before_url: Option<String>,
+    /// Shown once the link has ended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_url: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Destination<'a> {
+    Main,
+    Fallback(&'a str),
+    NotYetActive,
+    Ended,
+}
+
+impl Schedule {
+    pub fn is_empty(&self) -> bool {
+        *self == Schedule::default()
+    }
+
+    /// Describes what is wrong with the schedule, if anything
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if let (Some(not_before), Some(not_after)) = (self.not_before, self.not_after)
+            && not_before >= not_after
+        {
+            return Err("not_before must be earlier than not_after");
+        }
+        if self.before_url.is_some() && self.not_before.is_none() {
+            return Err("before_url requires not_before");
+        }
+        if self.after_url.is_some() && self.not_after.is_none() {
+            return Err("after_url requires not_after");
+        }
+        Ok(())
+    }
+
+    pub fn destination(&self, now: u64) -> Destination<'_> {
+        if self.not_before.is_some_and(|not_before| now < not_before) {
+            return match &self.before_url {
+                Some(url) => Destination::Fallback(url),
+                None => Destination::NotYetActive,
+            };
+        }
+        if self.not_after.is_some_and(|not_after| now >= not_after) {
+            return match &self.after_url {
+                Some(url) => Destination::Fallback(url),
+                None => Destination::Ended,
+            };
+        }
+        Destination::Main
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use std::time::Duration;
+
+    const DAY: Duration = Duration::from_secs(24 * 3600);
+
+    #[test]
+    fn test_destination() {
+        let clock = Clock::manual(1_700_000_000);
+        let launch = clock.now() + DAY.as_secs();
+        let schedule = Schedule {
+            not_before: Some(launch),
+            not_after: Some(launch + 7 * DAY.as_secs()),
+            before_url: Some("https://example.com/teaser".to_string()),
+            after_url: None,
+        };
+        assert!(schedule.validate().is_ok());
+
+        assert!(
+            schedule.destination(clock.now())
+                == Destination::Fallback("https://example.com/teaser")
+        );
+        clock.advance(DAY);
+        assert!(schedule.destination(clock.now()) == Destination::Main);
+        clock.advance(6 * DAY);
+        assert!(schedule.destination(clock.now()) == Destination::Main);
+        clock.advance(DAY);
+        assert!(schedule.destination(clock.now()) == Destination::Ended);
+
+        let open_ended = Schedule {
+            not_before: Some(launch),
+            ..Default::default()
+        };
+        assert!(open_ended.destination(launch - 1) == Destination::NotYetActive);
+        assert!(open_ended.destination(u64::MAX) == Destination::Main);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Schedule::default().validate().is_ok());
+        assert!(
+            Schedule {
+                not_before: Some(10),
+                not_after: Some(10),
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            Schedule {
+                after_url: Some("https://example.com/".to_string()),
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+const MAX_VARIANTS: usize = 16;
+const COOKIE_PREFIX: &str = "rurl_variant_";
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Variant {
+    pub url: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Shown in metrics and stored in the sticky cookie, the index is used otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Weighted destinations of an A/B experiment or a rotation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Split {
+    pub variants: Vec<Variant>,
+    /// Returning visitors get the same variant, remembered in a cookie
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sticky: bool,
+}
+
+impl Split {
+    /// Describes what is wrong with the split, if anything
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.variants.len() < 2 || self.variants.len() > MAX_VARIANTS {
+            return Err("Split needs from 2 to 16 variants");
+        }
+        if self.variants.iter().all(|variant| variant.weight == 0) {
+            return Err("At least one variant needs a positive weight");
+        }
+        // Names end up in the cookie and metric labels
+        let valid_name = |name: &str| {
+            (1..=32).contains(&name.len())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        };
+        if !self
+            .variants
+            .iter()
+            .filter_map(|variant| variant.name.as_deref())
+            .all(valid_name)
+        {
+            return Err("Variant names are 1-32 letters, digits, dashes or underscores");
+        }
+        let labels: std::collections::HashSet<_> =
+            (0..self.variants.len()).map(|i| self.label(i)).collect();
+        if labels.len() != self.variants.len() {
+            return Err("Variant names must be unique");
+        }
+        Ok(())
+    }
+
+    pub fn label(&self, index: usize) -> String {
+        self.variants[index]
+            .name
+            .clone()
+            .unwrap_or_else(|| index.to_string())
+    }
+
+    /// Keeps the remembered variant while it is still in rotation
+    pub fn pick(&self, remembered: Option<&str>) -> usize {
+        self.pick_with(remembered, &mut rand::rng())
+    }
+
+    fn pick_with(&self, remembered: Option<&str>, rng: &mut impl Rng) -> usize {
+        let remembered = remembered.and_then(|label| {
+            (0..self.variants.len())
+                .find(|&i| self.label(i) == label && self.variants[i].weight > 0)
+        });
+        if let Some(index) = remembered {
+            return index;
+        }
+        let total: u64 = self.variants.iter().map(|v| u64::from(v.weight)).sum();
+        let mut point = rng.random_range(0..total);
+        for (i, variant) in self.variants.iter().enumerate() {
+            if point < u64::from(variant.weight) {
+                return i;
+            }
+            point -= u64::from(variant.weight);
+        }
+        unreachable!("Point is below the total weight")
+    }
+}
+
+/// Slashes of namespaced links are not allowed in cookie names
+pub fn cookie_name(short: &str) -> String {
+    format!("{COOKIE_PREFIX}{}", short.replace('/', "."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn split(weights: &[u32]) -> Split {
+        Split {
+            variants: weights
+                .iter()
+                .enumerate()
+                .map(|(i, &weight)| Variant {
+                    url: format!("https://example.com/{i}"),
+                    weight,
+                    name: None,
+                })
+                .collect(),
+            sticky: true,
+        }
+    }
+
+    #[test]
+    fn test_weighted_pick() {
+        let split = split(&[1, 3, 0]);
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut picks = [0; 3];
+        for _ in 0..4000 {
+            picks[split.pick_with(None, &mut rng)] += 1;
+        }
+        assert!(picks[2] == 0);
+        assert!((800..1200).contains(&picks[0]), "{picks:?}");
+        assert!((2800..3200).contains(&picks[1]), "{picks:?}");
+    }
+
+    #[test]
+    fn test_sticky_pick() {
+        let mut split = split(&[1, 1, 0]);
+        split.variants[1].name = Some("green".to_string());
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..100 {
+            assert!(split.pick_with(Some("green"), &mut rng) == 1);
+        }
+        // Paused and unknown variants are picked anew
+        assert!(split.pick_with(Some("2"), &mut rng) != 2);
+        assert!(split.pick_with(Some("purple"), &mut rng) < 2);
+        assert!(split.label(0) == "0" && split.label(1) == "green");
+        assert!(cookie_name("docs/wiki") == "rurl_variant_docs.wiki");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(split(&[1, 1]).validate().is_ok());
+        assert!(split(&[1]).validate().is_err());
+        assert!(split(&[0, 0]).validate().is_err());
+        assert!(split(&[1; 17]).validate().is_err());
+
+        let mut duplicate = split(&[1, 1]);
+        duplicate.variants[0].name = Some("1".to_string());
+        assert!(duplicate.validate().is_err());
+        duplicate.variants[0].name = Some("a; Path=/".to_string());
+        assert!(duplicate.validate().is_err());
+    }
+}
@@ -1,51 +1,276 @@
-mod redis;
+pub(crate) mod redis;
+
+use serde::{Deserialize, Serialize};
 
 use crate::config;
+use crate::namespace::Namespace;
+use crate::params::Params;
+use crate::preview::PagePreview;
+use crate::schedule::Schedule;
+use crate::split::Split;
+use crate::targeting::TargetRule;
+
+/// Everything stored for a short link.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<PagePreview>,
+    /// Argon2 hash of the password required to follow the link
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+    /// The link expires after this many redirects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+    /// Ordered alternative destinations by platform, language or country
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetRule>,
+    /// Weighted destinations, used when no targeting rule matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<Split>,
+    /// Query parameters added at redirect time, on top of the template ones
+    #[serde(default, skip_serializing_if = "Params::is_empty")]
+    pub params: Params,
+    /// Name of the parameter template, resolved at redirect time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub param_template: Option<String>,
+    /// Unix timestamp (seconds), unknown for links created by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// Warning shown on an interstitial page before redirecting, set by admins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interstitial: Option<String>,
+}
+
+impl Link {
+    pub fn new(url: String) -> Self {
+        Link {
+            url,
+            preview: None,
+            password_hash: None,
+            max_clicks: None,
+            schedule: None,
+            targets: vec![],
+            split: None,
+            params: Params::new(),
+            param_template: None,
+            created_at: None,
+            interstitial: None,
+        }
+    }
+
+    /// Redirects every visitor to `url` right away, with nothing to check or count
+    pub fn is_plain(&self) -> bool {
+        self.password_hash.is_none()
+            && self.max_clicks.is_none()
+            && self.schedule.is_none()
+            && self.targets.is_empty()
+            && self.split.is_none()
+            && self.params.is_empty()
+            && self.param_template.is_none()
+            && self.interstitial.is_none()
+    }
+}
 
 mod internal {
+    use super::Link;
     use crate::config;
+    use crate::namespace::Namespace;
     use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
     use tokio::sync::RwLock;
 
-    #[allow(unused)]
+    // Created once per process, so the size of the in-memory variant does not matter
+    #[allow(unused, clippy::large_enum_variant)]
     pub enum StorageInner {
-        NonPersistent(RwLock<HashMap<String, String>>),
+        // Links, counters of used clicks and namespace quotas, namespaces, and short links by url
+        NonPersistent(
+            RwLock<HashMap<String, Link>>,
+            RwLock<HashMap<String, AtomicU64>>,
+            RwLock<HashMap<String, Namespace>>,
+            RwLock<HashMap<String, String>>,
+        ),
         Redis(crate::storage::redis::RedisSingleConnection),
     }
 
+    async fn count_up_to(
+        counters: &RwLock<HashMap<String, AtomicU64>>,
+        key: &str,
+        max: u64,
+    ) -> bool {
+        let increment = |used: &AtomicU64| {
+            used.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < max).then_some(used + 1)
+            })
+            .is_ok()
+        };
+        if let Some(used) = counters.read().await.get(key) {
+            return increment(used);
+        }
+        increment(counters.write().await.entry(key.to_string()).or_default())
+    }
+
     impl StorageInner {
-        pub async fn store(&self, short: String, url: String) -> bool {
+        pub async fn store(&self, short: String, link: Link) -> bool {
             match self {
-                StorageInner::NonPersistent(rw_lock) => {
+                StorageInner::NonPersistent(rw_lock, _, _, _) => {
                     let mut guard = rw_lock.write().await;
                     if let std::collections::hash_map::Entry::Vacant(e) = guard.entry(short) {
-                        e.insert(url);
+                        e.insert(link);
                         true
                     } else {
                         false
                     }
                 }
                 StorageInner::Redis(redis_single_connection) => {
-                    redis_single_connection.store(short, url).await
+                    redis_single_connection.store(short, link).await
+                }
+            }
+        }
+
+        pub async fn update(&self, short: &str, link: Link) -> bool {
+            match self {
+                StorageInner::NonPersistent(rw_lock, _, _, _) => {
+                    match rw_lock.write().await.get_mut(short) {
+                        Some(stored) => {
+                            *stored = link;
+                            true
+                        }
+                        None => false,
+                    }
+                }
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection.update(short, link).await
                 }
             }
         }
 
-        pub async fn fetch(&self, short: &str) -> Option<String> {
+        pub async fn fetch(&self, short: &str) -> Option<Link> {
             match self {
-                StorageInner::NonPersistent(rw_lock) => rw_lock.read().await.get(short).cloned(),
+                StorageInner::NonPersistent(rw_lock, _, _, _) => {
+                    rw_lock.read().await.get(short).cloned()
+                }
                 StorageInner::Redis(redis_single_connection) => {
                     redis_single_connection.fetch(short).await
                 }
             }
         }
 
+        pub async fn register_click(&self, short: &str, max_clicks: u64) -> bool {
+            match self {
+                StorageInner::NonPersistent(_, counters, _, _) => {
+                    count_up_to(counters, short, max_clicks).await
+                }
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection
+                        .register_click(short, max_clicks)
+                        .await
+                }
+            }
+        }
+
+        pub async fn consume_quota(&self, namespace: &str, max_links: u64) -> bool {
+            match self {
+                // Link keys have no colons, so quotas never share a counter with clicks
+                StorageInner::NonPersistent(_, counters, _, _) => {
+                    count_up_to(counters, &format!("quota:{namespace}"), max_links).await
+                }
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection
+                        .consume_quota(namespace, max_links)
+                        .await
+                }
+            }
+        }
+
+        pub async fn release_quota(&self, namespace: &str) {
+            match self {
+                StorageInner::NonPersistent(_, counters, _, _) => {
+                    if let Some(used) = counters.read().await.get(&format!("quota:{namespace}")) {
+                        let _ = used.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                            used.checked_sub(1)
+                        });
+                    }
+                }
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection.release_quota(namespace).await
+                }
+            }
+        }
+
+        pub async fn index_url(&self, url: String, short: String) {
+            match self {
+                StorageInner::NonPersistent(_, _, _, urls) => {
+                    urls.write().await.insert(url, short);
+                }
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection.index_url(&url, &short).await
+                }
+            }
+        }
+
+        pub async fn short_by_url(&self, url: &str) -> Option<String> {
+            match self {
+                StorageInner::NonPersistent(_, _, _, urls) => urls.read().await.get(url).cloned(),
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection.short_by_url(url).await
+                }
+            }
+        }
+
+        pub async fn store_namespace(&self, namespace: Namespace) -> bool {
+            match self {
+                StorageInner::NonPersistent(_, _, namespaces, _) => {
+                    let mut guard = namespaces.write().await;
+                    if guard.contains_key(&namespace.name) {
+                        return false;
+                    }
+                    guard.insert(namespace.name.clone(), namespace);
+                    true
+                }
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection.store_namespace(&namespace).await
+                }
+            }
+        }
+
+        pub async fn fetch_namespace(&self, name: &str) -> Option<Namespace> {
+            match self {
+                StorageInner::NonPersistent(_, _, namespaces, _) => {
+                    namespaces.read().await.get(name).cloned()
+                }
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection.fetch_namespace(name).await
+                }
+            }
+        }
+
+        pub async fn list_namespaces(&self) -> Vec<Namespace> {
+            let mut namespaces = match self {
+                StorageInner::NonPersistent(_, _, namespaces, _) => {
+                    namespaces.read().await.values().cloned().collect()
+                }
+                StorageInner::Redis(redis_single_connection) => {
+                    redis_single_connection.list_namespaces().await
+                }
+            };
+            namespaces.sort_by(|a: &Namespace, b| a.name.cmp(&b.name));
+            namespaces
+        }
+
         pub async fn from_config(config: &config::Config) -> Self {
             match &config.redis_endpoint {
                 Some(endpoint) => StorageInner::Redis(
                     crate::storage::redis::RedisSingleConnection::new(endpoint.to_string()).await,
                 ),
-                None => StorageInner::NonPersistent(RwLock::new(HashMap::default())),
+                None => StorageInner::NonPersistent(
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                ),
             }
         }
     }
@@ -54,14 +279,58 @@ mod internal {
 pub struct Storage(internal::StorageInner);
 
 impl Storage {
-    pub async fn store(&self, short: String, url: String) -> bool {
-        self.0.store(short, url).await
+    pub async fn store(&self, short: String, link: Link) -> bool {
+        self.0.store(short, link).await
     }
 
-    pub async fn fetch(&self, short: &str) -> Option<String> {
+    pub async fn fetch(&self, short: &str) -> Option<Link> {
         self.0.fetch(short).await
     }
 
+    /// Replaces an existing link, returns `false` when there is no such link
+    pub async fn update(&self, short: &str, link: Link) -> bool {
+        self.0.update(short, link).await
+    }
+
+    /// Counts a click of a link limited to `max_clicks`, returns `false` once the limit is reached
+    pub async fn register_click(&self, short: &str, max_clicks: u64) -> bool {
+        self.0.register_click(short, max_clicks).await
+    }
+
+    /// Counts a link created in the namespace, returns `false` once its quota is used up
+    pub async fn consume_quota(&self, namespace: &str, max_links: u64) -> bool {
+        self.0.consume_quota(namespace, max_links).await
+    }
+
+    /// Gives back a slot taken by `consume_quota` for a link which was not created
+    pub async fn release_quota(&self, namespace: &str) {
+        self.0.release_quota(namespace).await
+    }
+
+    /// Remembers the short link of a canonical url, so the url is not shortened twice
+    pub async fn index_url(&self, url: String, short: String) {
+        self.0.index_url(url, short).await
+    }
+
+    /// Short link stored for a canonical url by `index_url`, it may be gone since then
+    pub async fn short_by_url(&self, url: &str) -> Option<String> {
+        self.0.short_by_url(url).await
+    }
+
+    /// Stores a namespace unless one with the same name exists
+    pub async fn store_namespace(&self, namespace: Namespace) -> bool {
+        self.0.store_namespace(namespace).await
+    }
+
+    pub async fn fetch_namespace(&self, name: &str) -> Option<Namespace> {
+        self.0.fetch_namespace(name).await
+    }
+
+    /// Namespaces sorted by name
+    pub async fn list_namespaces(&self) -> Vec<Namespace> {
+        self.0.list_namespaces().await
+    }
+
     pub async fn from_config(config: &config::Config) -> Self {
         Storage(internal::StorageInner::from_config(config).await)
     }
@@ -73,9 +342,61 @@ mod tests {
 
     #[tokio::test]
     async fn test_storage() {
-        let storage = internal::StorageInner::NonPersistent(Default::default());
-        assert!(storage.store("key".into(), "val".into()).await);
-        assert!(!storage.store("key".into(), "val2".into()).await);
-        assert!(storage.fetch("key").await == Some("val".into()));
+        let storage = internal::StorageInner::NonPersistent(
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        );
+        assert!(storage.store("key".into(), Link::new("val".into())).await);
+        assert!(!storage.store("key".into(), Link::new("val2".into())).await);
+        assert!(storage.fetch("key").await == Some(Link::new("val".into())));
+        assert!(storage.update("key", Link::new("val3".into())).await);
+        assert!(storage.fetch("key").await == Some(Link::new("val3".into())));
+        assert!(!storage.update("missing", Link::new("val".into())).await);
+
+        assert!(storage.short_by_url("https://vk.com/").await.is_none());
+        storage
+            .index_url("https://vk.com/".into(), "key".into())
+            .await;
+        assert!(storage.short_by_url("https://vk.com/").await.as_deref() == Some("key"));
+    }
+
+    #[tokio::test]
+    async fn test_register_click() {
+        let storage = std::sync::Arc::new(Storage::from_config(&config::Config::default()).await);
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let storage = storage.clone();
+                tokio::spawn(async move { storage.register_click("invite", 5).await })
+            })
+            .collect();
+        let mut allowed = 0;
+        for task in tasks {
+            allowed += task.await.unwrap() as usize;
+        }
+        assert!(allowed == 5);
+        assert!(!storage.register_click("invite", 5).await);
+        assert!(storage.register_click("other", 1).await);
+        assert!(!storage.register_click("other", 1).await);
+    }
+
+    #[tokio::test]
+    async fn test_namespaces() {
+        let storage = Storage::from_config(&config::Config::default()).await;
+        let (docs, _) = Namespace::new("docs".to_string(), Some(2), None, 0);
+        let (ops, _) = Namespace::new("ops".to_string(), None, None, 0);
+        assert!(storage.store_namespace(ops.clone()).await);
+        assert!(storage.store_namespace(docs.clone()).await);
+        assert!(!storage.store_namespace(docs.clone()).await);
+        assert!(storage.fetch_namespace("docs").await == Some(docs.clone()));
+        assert!(storage.fetch_namespace("missing").await.is_none());
+        assert!(storage.list_namespaces().await == vec![docs, ops]);
+
+        assert!(storage.consume_quota("docs", 2).await);
+        assert!(storage.consume_quota("docs", 2).await);
+        assert!(!storage.consume_quota("docs", 2).await);
+        // Quotas do not share counters with clicks of a link named the same
+        assert!(storage.register_click("docs", 1).await);
     }
 }
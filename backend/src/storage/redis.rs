@@ -1,5 +1,49 @@
 use tokio::sync::Mutex;
 
+use crate::namespace::Namespace;
+use crate::storage::Link;
+
+// Click counters expire together with their link (KEYS[2]), quotas have no TTL. INCR makes
+// concurrent requests never exceed the limit, refused ones are taken back so released quota
+// slots become available again
+const COUNT_UP_TO_SCRIPT: &str = r"
+local used = redis.call('INCR', KEYS[1])
+if used == 1 and KEYS[2] then
+    local ttl = redis.call('TTL', KEYS[2])
+    if ttl > 0 then
+        redis.call('EXPIRE', KEYS[1], ttl)
+    end
+end
+if used > tonumber(ARGV[1]) then
+    redis.call('DECR', KEYS[1])
+    return 0
+end
+return 1
+";
+const NAMESPACES_KEY: &str = "rurl:namespaces";
+const LINK_TTL: u64 = 3600;
+// Redis keeps expiry times in milliseconds and refuses timestamps which overflow them
+const MAX_EXPIRE_AT: u64 = i64::MAX as u64 / 1000;
+
+#[derive(Debug, PartialEq)]
+enum Expiry {
+    In(u64),
+    At(u64),
+}
+
+/// Scheduled links are kept until they have ended, for good when they never end or end later
+/// than Redis can expire them
+fn expiry(link: &Link) -> Option<Expiry> {
+    match &link.schedule {
+        None => Some(Expiry::In(LINK_TTL)),
+        Some(schedule) => schedule
+            .not_after
+            .and_then(|not_after| not_after.checked_add(LINK_TTL))
+            .filter(|timestamp| *timestamp <= MAX_EXPIRE_AT)
+            .map(Expiry::At),
+    }
+}
+
 // TODO use pool of connections instead of one
 pub(crate) struct RedisSingleConnection {
     conn: Mutex<redis::aio::MultiplexedConnection>,
@@ -20,9 +64,47 @@ impl RedisSingleConnection {
         }
     }
 
-    pub(crate) async fn store(&self, short: String, url: String) -> bool {
-        // Set key=short with value=url if not set yet atomically
-        // TODO think about for how much time to store?
+    pub(crate) async fn store(&self, short: String, link: Link) -> bool {
+        // Set key=short with value=link if not set yet atomically
+        let value = match serde_json::to_string(&link) {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("Cannot serialize link: {}", e);
+                return false;
+            }
+        };
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(short).arg(value).arg("NX");
+        match expiry(&link) {
+            Some(Expiry::In(seconds)) => cmd.arg("EX").arg(seconds),
+            Some(Expiry::At(timestamp)) => cmd.arg("EXAT").arg(timestamp),
+            None => &mut cmd,
+        };
+        match self.conn.lock().await.send_packed_command(&cmd).await {
+            Ok(resp) => match resp {
+                redis::Value::Okay => true,
+                redis::Value::Nil => false,
+                _ => {
+                    log::warn!("Response from redis store is not OK, nor nil");
+                    false
+                }
+            },
+            Err(e) => {
+                log::error!("Error to store in redis: {}", e);
+                false
+            }
+        }
+    }
+
+    pub(crate) async fn update(&self, short: &str, link: Link) -> bool {
+        let value = match serde_json::to_string(&link) {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("Cannot serialize link: {}", e);
+                return false;
+            }
+        };
+        // Only existing links are replaced, their expiration is kept
         match self
             .conn
             .lock()
@@ -30,29 +112,26 @@ impl RedisSingleConnection {
             .send_packed_command(
                 redis::cmd("SET")
                     .arg(short)
-                    .arg(url)
-                    .arg("EX")
-                    .arg(3600)
-                    .arg("NX"),
+                    .arg(value)
+                    .arg("KEEPTTL")
+                    .arg("XX"),
             )
             .await
         {
-            Ok(resp) => match resp {
-                redis::Value::Okay => true,
-                redis::Value::Nil => false,
-                _ => {
-                    log::warn!("Response from redis store is not OK, nor nil");
-                    false
-                }
-            },
+            Ok(redis::Value::Okay) => true,
+            Ok(redis::Value::Nil) => false,
+            Ok(_) => {
+                log::warn!("Response from redis update is not OK, nor nil");
+                false
+            }
             Err(e) => {
-                log::error!("Error to store in redis: {}", e);
+                log::error!("Error to update in redis: {}", e);
                 false
             }
         }
     }
 
-    pub(crate) async fn fetch(&self, short: &str) -> Option<String> {
+    pub(crate) async fn fetch(&self, short: &str) -> Option<Link> {
         match self
             .conn
             .lock()
@@ -61,10 +140,10 @@ impl RedisSingleConnection {
             .await
         {
             Ok(resp) => match resp {
-                redis::Value::SimpleString(s) => Some(s),
+                redis::Value::SimpleString(s) => Some(decode_link(s)),
                 redis::Value::Nil => None,
                 redis::Value::BulkString(s) => match String::from_utf8(s) {
-                    Ok(s) => Some(s),
+                    Ok(s) => Some(decode_link(s)),
                     Err(_) => {
                         log::warn!("Non utf-8 url is stored");
                         None
@@ -81,4 +160,292 @@ impl RedisSingleConnection {
             }
         }
     }
+
+    pub(crate) async fn index_url(&self, url: &str, short: &str) {
+        // Expires with the link itself
+        if let Err(e) = self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(
+                redis::cmd("SET")
+                    .arg(format!("rurl:url:{url}"))
+                    .arg(short)
+                    .arg("EX")
+                    .arg(LINK_TTL),
+            )
+            .await
+        {
+            log::error!("Error to index url in redis: {}", e);
+        }
+    }
+
+    pub(crate) async fn short_by_url(&self, url: &str) -> Option<String> {
+        match self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(redis::cmd("GET").arg(format!("rurl:url:{url}")))
+            .await
+        {
+            Ok(redis::Value::BulkString(s)) => String::from_utf8(s).ok(),
+            Ok(redis::Value::Nil) => None,
+            Ok(_) => {
+                log::warn!("Indexed short link is not string");
+                None
+            }
+            Err(e) => {
+                log::error!("Error to fetch indexed url from redis: {}", e);
+                None
+            }
+        }
+    }
+
+    pub(crate) async fn register_click(&self, short: &str, max_clicks: u64) -> bool {
+        self.count_up_to(format!("rurl:clicks:{short}"), max_clicks, Some(short))
+            .await
+    }
+
+    pub(crate) async fn consume_quota(&self, namespace: &str, max_links: u64) -> bool {
+        self.count_up_to(format!("rurl:quota:{namespace}"), max_links, None)
+            .await
+    }
+
+    pub(crate) async fn release_quota(&self, namespace: &str) {
+        if let Err(e) = self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(redis::cmd("DECR").arg(format!("rurl:quota:{namespace}")))
+            .await
+        {
+            log::error!("Error to release quota in redis: {}", e);
+        }
+    }
+
+    async fn count_up_to(&self, key: String, max: u64, link: Option<&str>) -> bool {
+        let mut cmd = redis::cmd("EVAL");
+        cmd.arg(COUNT_UP_TO_SCRIPT)
+            .arg(1 + usize::from(link.is_some()))
+            .arg(key)
+            .arg(link)
+            .arg(max);
+        match self.conn.lock().await.send_packed_command(&cmd).await {
+            Ok(redis::Value::Int(allowed)) => allowed == 1,
+            Ok(_) => {
+                log::warn!("Response from redis counter script is not integer");
+                false
+            }
+            Err(e) => {
+                log::error!("Error to count in redis: {}", e);
+                false
+            }
+        }
+    }
+
+    pub(crate) async fn store_namespace(&self, namespace: &Namespace) -> bool {
+        let value = match serde_json::to_string(namespace) {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("Cannot serialize namespace: {}", e);
+                return false;
+            }
+        };
+        match self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(
+                redis::cmd("HSETNX")
+                    .arg(NAMESPACES_KEY)
+                    .arg(&namespace.name)
+                    .arg(value),
+            )
+            .await
+        {
+            Ok(redis::Value::Int(created)) => created == 1,
+            Ok(_) => {
+                log::warn!("Response from redis HSETNX is not integer");
+                false
+            }
+            Err(e) => {
+                log::error!("Error to store namespace in redis: {}", e);
+                false
+            }
+        }
+    }
+
+    pub(crate) async fn fetch_namespace(&self, name: &str) -> Option<Namespace> {
+        match self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(redis::cmd("HGET").arg(NAMESPACES_KEY).arg(name))
+            .await
+        {
+            Ok(redis::Value::BulkString(value)) => decode_namespace(&value),
+            Ok(redis::Value::Nil) => None,
+            Ok(_) => {
+                log::warn!("Stored namespace is not string");
+                None
+            }
+            Err(e) => {
+                log::error!("Error to fetch namespace from redis: {}", e);
+                None
+            }
+        }
+    }
+
+    pub(crate) async fn list_namespaces(&self) -> Vec<Namespace> {
+        match self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(redis::cmd("HVALS").arg(NAMESPACES_KEY))
+            .await
+        {
+            Ok(redis::Value::Array(values)) => values
+                .into_iter()
+                .filter_map(|value| match value {
+                    redis::Value::BulkString(value) => decode_namespace(&value),
+                    _ => None,
+                })
+                .collect(),
+            Ok(_) => {
+                log::warn!("Response from redis HVALS is not array");
+                vec![]
+            }
+            Err(e) => {
+                log::error!("Error to list namespaces in redis: {}", e);
+                vec![]
+            }
+        }
+    }
+
+    pub(crate) async fn pop_from_set(&self, set: &str) -> Option<String> {
+        match self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(redis::cmd("SPOP").arg(set))
+            .await
+        {
+            Ok(redis::Value::BulkString(s)) => String::from_utf8(s).ok(),
+            Ok(redis::Value::Nil) => None,
+            Ok(_) => {
+                log::warn!("Set member is not string");
+                None
+            }
+            Err(e) => {
+                log::error!("Error to pop from redis set: {}", e);
+                None
+            }
+        }
+    }
+
+    pub(crate) async fn add_to_set(&self, set: &str, members: &[String]) -> bool {
+        if members.is_empty() {
+            return true;
+        }
+        match self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(redis::cmd("SADD").arg(set).arg(members))
+            .await
+        {
+            Ok(_) => true,
+            Err(e) => {
+                log::error!("Error to add to redis set: {}", e);
+                false
+            }
+        }
+    }
+
+    pub(crate) async fn set_len(&self, set: &str) -> usize {
+        match self
+            .conn
+            .lock()
+            .await
+            .send_packed_command(redis::cmd("SCARD").arg(set))
+            .await
+        {
+            Ok(redis::Value::Int(len)) => len as usize,
+            Ok(_) => {
+                log::warn!("Response from redis SCARD is not integer");
+                0
+            }
+            Err(e) => {
+                log::error!("Error to get redis set size: {}", e);
+                0
+            }
+        }
+    }
+}
+
+// Links stored before the JSON format are plain urls, which are never valid JSON objects
+fn decode_link(value: String) -> Link {
+    serde_json::from_str(&value).unwrap_or_else(|_| Link::new(value))
+}
+
+fn decode_namespace(value: &[u8]) -> Option<Namespace> {
+    serde_json::from_slice(value)
+        .inspect_err(|e| log::warn!("Stored namespace is invalid: {e}"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::PagePreview;
+    use crate::schedule::Schedule;
+
+    #[test]
+    fn test_decode_link() {
+        assert!(
+            decode_link("https://example.com".into()) == Link::new("https://example.com".into())
+        );
+
+        let link = Link {
+            url: "https://example.com".into(),
+            preview: Some(PagePreview {
+                title: Some("Example".into()),
+                ..Default::default()
+            }),
+            password_hash: Some("$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".into()),
+            max_clicks: Some(1),
+            schedule: None,
+            targets: vec![],
+            split: None,
+            params: Default::default(),
+            param_template: None,
+            created_at: None,
+            interstitial: None,
+        };
+        assert!(decode_link(serde_json::to_string(&link).unwrap()) == link);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut link = Link::new("https://example.com".into());
+        assert!(expiry(&link) == Some(Expiry::In(LINK_TTL)));
+        link.schedule = Some(Schedule {
+            not_before: Some(1_700_000_000),
+            ..Default::default()
+        });
+        assert!(expiry(&link).is_none());
+        link.schedule = Some(Schedule {
+            not_after: Some(1_800_000_000),
+            ..Default::default()
+        });
+        assert!(expiry(&link) == Some(Expiry::At(1_800_000_000 + LINK_TTL)));
+
+        for not_after in [MAX_EXPIRE_AT, i64::MAX as u64 + 1, u64::MAX] {
+            link.schedule = Some(Schedule {
+                not_after: Some(not_after),
+                ..Default::default()
+            });
+            assert!(expiry(&link).is_none());
+        }
+    }
 }
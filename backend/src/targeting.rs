@@ -0,0 +1,236 @@
+use std::net::IpAddr;
+
+use maxminddb::geoip2;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Ios,
+    Android,
+    /// Any phone or tablet, iOS and Android included
+    Mobile,
+    Desktop,
+}
+
+/// Alternative destination for visitors matching every given condition.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TargetRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    /// Primary language of the visitor, `de` matches `de-AT` as well
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// ISO 3166-1 alpha-2 code, needs a GeoIP database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    pub url: String,
+}
+
+impl TargetRule {
+    fn matches(&self, visitor: &Visitor) -> bool {
+        self.platform
+            .is_none_or(|platform| visitor.is_platform(platform))
+            && self.language.as_ref().is_none_or(|language| {
+                visitor.language.as_ref().is_some_and(|preferred| {
+                    let language = language.to_lowercase();
+                    *preferred == language || preferred.starts_with(&format!("{language}-"))
+                })
+            })
+            && self.country.as_ref().is_none_or(|country| {
+                visitor
+                    .country
+                    .as_ref()
+                    .is_some_and(|visitor_country| visitor_country.eq_ignore_ascii_case(country))
+            })
+    }
+}
+
+/// What is known about the client following a link.
+#[derive(Debug, Default)]
+pub struct Visitor {
+    pub platform: Option<Platform>,
+    /// Most preferred language tag, lowercased
+    pub language: Option<String>,
+    pub country: Option<String>,
+}
+
+impl Visitor {
+    pub fn new(user_agent: &str, accept_language: &str, country: Option<String>) -> Self {
+        Visitor {
+            platform: detect_platform(user_agent),
+            language: preferred_language(accept_language),
+            country,
+        }
+    }
+
+    fn is_platform(&self, platform: Platform) -> bool {
+        match (self.platform, platform) {
+            (Some(Platform::Ios | Platform::Android), Platform::Mobile) => true,
+            (Some(own), platform) => own == platform,
+            (None, _) => false,
+        }
+    }
+}
+
+/// First matching rule wins, `None` means the default destination
+pub fn select<'a>(rules: &'a [TargetRule], visitor: &Visitor) -> Option<&'a str> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(visitor))
+        .map(|rule| rule.url.as_str())
+}
+
+fn detect_platform(user_agent: &str) -> Option<Platform> {
+    if user_agent.is_empty() {
+        return None;
+    }
+    let platform = if ["iPhone", "iPad", "iPod"]
+        .iter()
+        .any(|device| user_agent.contains(device))
+    {
+        Platform::Ios
+    } else if user_agent.contains("Android") {
+        Platform::Android
+    } else if user_agent.contains("Mobile") {
+        Platform::Mobile
+    } else {
+        Platform::Desktop
+    };
+    Some(platform)
+}
+
+// `de-AT,de;q=0.9,en;q=0.5` -> `de-at`
+fn preferred_language(accept_language: &str) -> Option<String> {
+    accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((tag, quality))
+        })
+        // The first of equally weighted tags is kept
+        .fold(
+            None,
+            |best: Option<(String, f32)>, (tag, quality)| match best {
+                Some((_, best_quality)) if best_quality >= quality => best,
+                _ => Some((tag, quality)),
+            },
+        )
+        .map(|(tag, _)| tag)
+}
+
+/// Country lookup in a local MaxMind database.
+pub struct GeoIp {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    pub fn from_config(config: &Config) -> Result<Option<Self>, maxminddb::MaxMindDBError> {
+        let Some(path) = &config.geoip_database else {
+            return Ok(None);
+        };
+        Ok(Some(GeoIp {
+            reader: maxminddb::Reader::open_readfile(path)?,
+        }))
+    }
+
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        self.reader
+            .lookup::<geoip2::Country>(ip)
+            .ok()?
+            .country?
+            .iso_code
+            .map(str::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPHONE: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1";
+    const ANDROID: &str = "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0 Mobile Safari/537.36";
+    const DESKTOP: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0 Safari/537.36";
+
+    #[test]
+    fn test_visitor() {
+        assert!(detect_platform(IPHONE) == Some(Platform::Ios));
+        assert!(detect_platform(ANDROID) == Some(Platform::Android));
+        assert!(detect_platform(DESKTOP) == Some(Platform::Desktop));
+        assert!(detect_platform("").is_none());
+
+        assert!(preferred_language("de-AT,de;q=0.9,en;q=0.5").as_deref() == Some("de-at"));
+        assert!(preferred_language("en;q=0.5, fr").as_deref() == Some("fr"));
+        assert!(preferred_language("ru;q=0.8,uk;q=0.8").as_deref() == Some("ru"));
+        assert!(preferred_language("*").is_none());
+        assert!(preferred_language("").is_none());
+    }
+
+    #[test]
+    fn test_select() {
+        let rule =
+            |platform, language: Option<&str>, country: Option<&str>, url: &str| TargetRule {
+                platform,
+                language: language.map(str::to_string),
+                country: country.map(str::to_string),
+                url: url.to_string(),
+            };
+        let rules = [
+            rule(
+                Some(Platform::Ios),
+                Some("de"),
+                None,
+                "https://apps.apple.com/de/app",
+            ),
+            rule(
+                Some(Platform::Ios),
+                None,
+                None,
+                "https://apps.apple.com/app",
+            ),
+            rule(
+                Some(Platform::Android),
+                None,
+                None,
+                "https://play.google.com/app",
+            ),
+            rule(Some(Platform::Mobile), None, None, "https://m.example.com/"),
+            rule(None, None, Some("FR"), "https://example.fr/"),
+        ];
+
+        let select_for = |user_agent, language, country: Option<&str>| {
+            select(
+                &rules,
+                &Visitor::new(user_agent, language, country.map(str::to_string)),
+            )
+        };
+        assert!(
+            select_for(IPHONE, "de-DE,en;q=0.5", None) == Some("https://apps.apple.com/de/app")
+        );
+        assert!(select_for(IPHONE, "en-US", None) == Some("https://apps.apple.com/app"));
+        assert!(select_for(ANDROID, "", None) == Some("https://play.google.com/app"));
+        assert!(
+            select_for("Opera/9.80 (J2ME/MIDP; Opera Mini) Mobile", "", None)
+                == Some("https://m.example.com/")
+        );
+        assert!(select_for(DESKTOP, "fr", Some("fr")) == Some("https://example.fr/"));
+        assert!(select_for(DESKTOP, "fr", None).is_none());
+        assert!(select_for("", "", None).is_none());
+    }
+
+    #[test]
+    fn test_geoip_disabled_by_default() {
+        assert!(GeoIp::from_config(&Config::default()).unwrap().is_none());
+        let config = Config {
+            geoip_database: Some("/nonexistent/GeoLite2-Country.mmdb".to_string()),
+            ..Default::default()
+        };
+        assert!(GeoIp::from_config(&config).is_err());
+    }
+}
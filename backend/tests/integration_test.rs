@@ -1,8 +1,19 @@
-use std::{collections::HashMap, str::Utf8Error, sync::Once, time::Duration};
+use std::{
+    collections::HashMap,
+    str::Utf8Error,
+    sync::{Arc, Once},
+    time::Duration,
+};
 
 use rand::Rng;
 use reqwest::StatusCode;
-use rurl_shortener::{app::App, config::Config};
+use rurl_shortener::{
+    app::App,
+    clock::Clock,
+    config::Config,
+    qr::{self, QrOptions},
+    resources::Resources,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::{sync::RwLock, time::timeout};
@@ -91,7 +102,10 @@ fn inmemory_random_config() -> Config {
         port: 0,
         host: "127.0.0.1".to_string(),
         redis_endpoint: None,
-        openrouter_token: None,
+        llm_api_key: None,
+        // Destinations are not resolved, so the tests do not depend on DNS
+        allow_private_destinations: true,
+        ..Default::default()
     }
 }
 
@@ -111,8 +125,14 @@ fn logger_init() {
 
 #[must_use]
 async fn app_init() -> (Connection, App) {
-    let config = inmemory_random_config();
-    let app = App::from_config(&config).await;
+    app_init_with(inmemory_random_config()).await
+}
+
+#[must_use]
+async fn app_init_with(config: Config) -> (Connection, App) {
+    let app = App::from_config(&config)
+        .await
+        .expect("Invalid configuration");
     let addr = app.get_addr().expect("Cannot get local addr");
 
     let client = no_redirect_client();
@@ -148,7 +168,7 @@ async fn shorten_one_link() {
     logger_init();
 
     let (conn, app) = app_init().await;
-    let full_link = "https://vk.com";
+    let full_link = "https://vk.com/";
 
     let script = async {
         let shorten_resp = conn
@@ -178,8 +198,8 @@ async fn shorten_multiple_links_concurrently() {
 
     let (conn, app) = app_init().await;
     let full_links = vec![
-        "https://vk.com".to_string(),
-        "http://example.com".to_string(),
+        "https://vk.com/".to_string(),
+        "http://example.com/".to_string(),
         "https://yandex.ru/search?text=rust".to_string(),
         "https://api.github.com/users/rust-lang/repos?sort=updated&page=2".to_string(),
         "https://en.wikipedia.org/wiki/Main_Page".to_string(),
@@ -249,3 +269,941 @@ async fn shorten_multiple_links_concurrently() {
 
     assert!(script_res.is_ok());
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn shorten_with_custom_alias() {
+    logger_init();
+
+    let (conn, app) = app_init().await;
+    let full_link = "https://vk.com/";
+
+    let script = async {
+        let shorten = |alias: &'static str| {
+            conn.client
+                .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+                .json(&HashMap::from([("url", full_link), ("alias", alias)]))
+                .send()
+        };
+
+        let response = shorten("my_vk_page").await.expect("Cannot shorten link");
+        assert!(response.status() == StatusCode::OK);
+        let shorten_resp: ShortenResponse = response.json().await.expect("Invalid response");
+        assert!(shorten_resp.short == "my_vk_page");
+        let returned_full_link = conn
+            .get_link_request(shorten_resp.short)
+            .await
+            .expect("Cannot get full link back");
+        assert!(returned_full_link == full_link);
+
+        let response = shorten("my_vk_page").await.expect("Cannot send request");
+        assert!(response.status() == StatusCode::CONFLICT);
+
+        let response = shorten("metrics").await.expect("Cannot send request");
+        assert!(response.status() == StatusCode::BAD_REQUEST);
+
+        let response = shorten("no/slashes").await.expect("Cannot send request");
+        assert!(response.status() == StatusCode::BAD_REQUEST);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn shorten_canonicalizes_url() {
+    logger_init();
+
+    let (conn, app) = app_init().await;
+
+    let script = async {
+        let shorten_resp = conn
+            .shorten_request(ShortenRequest {
+                url: "HTTPS://VK.com:443/a/../%7efeed".to_string(),
+            })
+            .await
+            .expect("Cannot shorten link");
+        let returned_full_link = conn
+            .get_link_request(shorten_resp.short.clone())
+            .await
+            .expect("Cannot get full link back");
+        assert!(returned_full_link == "https://vk.com/~feed");
+
+        // The same destination in another spelling gets the same short link
+        let again = conn
+            .shorten_request(ShortenRequest {
+                url: "https://vk.com/~feed".to_string(),
+            })
+            .await
+            .expect("Cannot shorten link");
+        assert!(again.short == shorten_resp.short);
+        // Unless the link behaves differently
+        let response = conn
+            .client
+            .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+            .json(&serde_json::json!({"url": "https://vk.com/~feed", "max_clicks": 1}))
+            .send()
+            .await
+            .unwrap();
+        let limited: serde_json::Value = response.json().await.unwrap();
+        assert!(limited["short"] != shorten_resp.short.as_str());
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn password_protected_link() {
+    logger_init();
+
+    let (conn, app) = app_init_with(Config {
+        trust_forwarded_for: true,
+        ..inmemory_random_config()
+    })
+    .await;
+    let full_link = "https://vk.com/";
+
+    let script = async {
+        let response = conn
+            .client
+            .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+            .json(&HashMap::from([("url", full_link), ("password", "s3cret")]))
+            .send()
+            .await
+            .expect("Cannot shorten link");
+        let shorten_resp: ShortenResponse = response.json().await.expect("Invalid response");
+        let link_url = format!(
+            "http://{}:{}/api/v1/{}",
+            conn.host, conn.port, shorten_resp.short
+        );
+
+        // Interstitial form instead of the redirect
+        let response = conn.client.get(&link_url).send().await.unwrap();
+        assert!(response.status() == StatusCode::OK);
+        assert!(response.headers().get("Location").is_none());
+        assert!(
+            response
+                .text()
+                .await
+                .unwrap()
+                .contains("<form method=\"post\">")
+        );
+
+        let unlock_from = |client: &'static str, password: &'static str| {
+            conn.client
+                .post(&link_url)
+                .header("X-Forwarded-For", client)
+                .form(&HashMap::from([("password", password)]))
+                .send()
+        };
+        let unlock = |password| unlock_from("203.0.113.7", password);
+        let response = unlock("wrong").await.unwrap();
+        assert!(response.status() == StatusCode::UNAUTHORIZED);
+
+        let response = unlock("s3cret").await.unwrap();
+        assert!(response.status() == StatusCode::SEE_OTHER);
+        assert!(response.headers()["Location"] == full_link);
+
+        // Default limit is 5 attempts per minute, 2 are already spent
+        for _ in 0..3 {
+            unlock("wrong").await.unwrap();
+        }
+        let response = unlock("s3cret").await.unwrap();
+        assert!(response.status() == StatusCode::TOO_MANY_REQUESTS);
+
+        // The proxy appends the real address, a forged one in front of it changes nothing
+        let response = unlock_from("198.51.100.1, 203.0.113.7", "s3cret")
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::TOO_MANY_REQUESTS);
+
+        // Attempts are counted per client, so others are not locked out
+        let response = unlock_from("198.51.100.1", "s3cret").await.unwrap();
+        assert!(response.status() == StatusCode::SEE_OTHER);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(3), app.run()),
+        timeout(Duration::from_secs(3), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn max_clicks_link() {
+    logger_init();
+
+    let (conn, app) = app_init().await;
+    let full_link = "https://vk.com/";
+
+    let script = async {
+        let response = conn
+            .client
+            .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+            .json(&serde_json::json!({"url": full_link, "max_clicks": 2}))
+            .send()
+            .await
+            .expect("Cannot shorten link");
+        let shorten_resp: ShortenResponse = response.json().await.expect("Invalid response");
+        let link_url = format!(
+            "http://{}:{}/api/v1/{}",
+            conn.host, conn.port, shorten_resp.short
+        );
+
+        for _ in 0..2 {
+            let response = conn.client.get(&link_url).send().await.unwrap();
+            assert!(response.status() == StatusCode::FOUND);
+            assert!(response.headers()["Location"] == full_link);
+        }
+        let response = conn.client.get(&link_url).send().await.unwrap();
+        assert!(response.status() == StatusCode::GONE);
+
+        let response = conn
+            .client
+            .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+            .json(&serde_json::json!({"url": full_link, "max_clicks": 0}))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::BAD_REQUEST);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn scheduled_link() {
+    logger_init();
+
+    let now = 1_700_000_000;
+    let clock = Arc::new(Clock::manual(now));
+    let config = inmemory_random_config();
+    let resources = Resources::load(&config).expect("Invalid configuration");
+    let app = App::new(&config, resources, clock.clone()).await;
+    let conn = Connection {
+        port: app.get_addr().expect("Cannot get local addr").port(),
+        host: config.host,
+        client: no_redirect_client(),
+    };
+
+    let script = async {
+        let shorten = |request: serde_json::Value| {
+            conn.client
+                .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+                .json(&request)
+                .send()
+        };
+        let get = |short: String| {
+            conn.client
+                .get(format!("http://{}:{}/api/v1/{short}", conn.host, conn.port))
+                .send()
+        };
+
+        let response = shorten(serde_json::json!({
+            "url": "https://vk.com/",
+            "not_before": now + 3600,
+            "before_url": "https://vk.com/teaser",
+        }))
+        .await
+        .unwrap();
+        let upcoming: ShortenResponse = response.json().await.expect("Invalid response");
+        let response = get(upcoming.short.clone()).await.unwrap();
+        assert!(response.status() == StatusCode::FOUND);
+        assert!(response.headers()["Location"] == "https://vk.com/teaser");
+
+        clock.advance(Duration::from_secs(3600));
+        let response = get(upcoming.short).await.unwrap();
+        assert!(response.status() == StatusCode::FOUND);
+        assert!(response.headers()["Location"] == "https://vk.com/");
+
+        let response = shorten(serde_json::json!({
+            "url": "https://vk.com/",
+            "not_before": now - 3600,
+            "not_after": now - 1,
+        }))
+        .await
+        .unwrap();
+        let ended: ShortenResponse = response.json().await.expect("Invalid response");
+        assert!(get(ended.short).await.unwrap().status() == StatusCode::GONE);
+
+        let response = shorten(serde_json::json!({
+            "url": "https://vk.com/",
+            "after_url": "https://vk.com/teaser",
+        }))
+        .await
+        .unwrap();
+        assert!(response.status() == StatusCode::BAD_REQUEST);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn platform_targeted_link() {
+    logger_init();
+
+    let (conn, app) = app_init().await;
+
+    let script = async {
+        let response = conn
+            .client
+            .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+            .json(&serde_json::json!({
+                "url": "https://vk.com/",
+                "targets": [
+                    {"platform": "ios", "url": "https://apps.apple.com/app/vk"},
+                    {"platform": "android", "language": "ru", "url": "https://play.google.com/store/apps/vk?hl=ru"},
+                ],
+            }))
+            .send()
+            .await
+            .expect("Cannot shorten link");
+        let shorten_resp: ShortenResponse = response.json().await.expect("Invalid response");
+        let link_url = format!(
+            "http://{}:{}/api/v1/{}",
+            conn.host, conn.port, shorten_resp.short
+        );
+        let location = |user_agent: &'static str, language: &'static str| {
+            let request = conn
+                .client
+                .get(&link_url)
+                .header("User-Agent", user_agent)
+                .header("Accept-Language", language);
+            async move {
+                let response = request.send().await.unwrap();
+                assert!(response.status() == StatusCode::FOUND);
+                response.headers()["Location"].to_str().unwrap().to_string()
+            }
+        };
+
+        assert!(
+            location("Mozilla/5.0 (iPhone; CPU iPhone OS 17_4)", "en").await
+                == "https://apps.apple.com/app/vk"
+        );
+        assert!(
+            location("Mozilla/5.0 (Linux; Android 14)", "ru-RU,en;q=0.5").await
+                == "https://play.google.com/store/apps/vk?hl=ru"
+        );
+        assert!(location("Mozilla/5.0 (Linux; Android 14)", "en").await == "https://vk.com/");
+        assert!(location("Mozilla/5.0 (X11; Linux x86_64)", "ru").await == "https://vk.com/");
+
+        let response = conn
+            .client
+            .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+            .json(&serde_json::json!({
+                "url": "https://vk.com/",
+                "targets": [{"country": "DE", "url": "https://vk.com/de"}],
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::BAD_REQUEST);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test]
+async fn split_link() {
+    logger_init();
+
+    let (conn, app) = app_init().await;
+
+    let script = async {
+        let response = conn
+            .client
+            .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+            .json(&serde_json::json!({
+                "url": "https://example.com/",
+                "split": {
+                    "variants": [
+                        {"url": "https://example.com/a", "weight": 1, "name": "a"},
+                        {"url": "https://example.com/b", "weight": 1, "name": "b"},
+                        {"url": "https://example.com/c", "weight": 0},
+                    ],
+                    "sticky": true,
+                },
+            }))
+            .send()
+            .await
+            .expect("Cannot shorten link");
+        let shorten_resp: ShortenResponse = response.json().await.expect("Invalid response");
+        let link_url = format!(
+            "http://{}:{}/api/v1/{}",
+            conn.host, conn.port, shorten_resp.short
+        );
+
+        let response = conn.client.get(&link_url).send().await.unwrap();
+        assert!(response.status() == StatusCode::FOUND);
+        let location = response.headers()["Location"].to_str().unwrap().to_string();
+        assert!(location == "https://example.com/a" || location == "https://example.com/b");
+        let cookie = response.headers()["Set-Cookie"].to_str().unwrap();
+        let (cookie, attributes) = cookie.split_once(';').unwrap();
+        assert!(attributes.starts_with(" Path=/;"));
+        assert!(cookie == format!("rurl_variant_{}={}", shorten_resp.short, &location[20..]));
+
+        // Returning visitor keeps the variant
+        for _ in 0..5 {
+            let response = conn
+                .client
+                .get(&link_url)
+                .header("Cookie", format!("other=1; {cookie}"))
+                .send()
+                .await
+                .unwrap();
+            assert!(response.headers()["Location"] == location.as_str());
+        }
+
+        let response = conn
+            .client
+            .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+            .json(&serde_json::json!({
+                "url": "https://example.com/",
+                "split": {"variants": [{"url": "https://example.com/a"}]},
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::BAD_REQUEST);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test]
+async fn link_with_params() {
+    logger_init();
+
+    let templates =
+        std::env::temp_dir().join(format!("rurl-it-params-{}.toml", std::process::id()));
+    std::fs::write(
+        &templates,
+        "[newsletter]\nutm_source = \"newsletter\"\nutm_medium = \"email\"\n",
+    )
+    .unwrap();
+    let (conn, app) = app_init_with(Config {
+        param_templates_file: Some(templates.to_string_lossy().to_string()),
+        ..inmemory_random_config()
+    })
+    .await;
+
+    let script = async {
+        let shorten = |body: serde_json::Value| {
+            let request = conn
+                .client
+                .post(format!("http://{}:{}/api/v1/", conn.host, conn.port))
+                .json(&body);
+            async move { request.send().await.unwrap() }
+        };
+        let location = |short: String| {
+            let request = conn
+                .client
+                .get(format!("http://{}:{}/api/v1/{short}", conn.host, conn.port));
+            async move {
+                let response = request.send().await.unwrap();
+                let status = response.status();
+                (
+                    status,
+                    response.headers()["Location"].to_str().unwrap().to_string(),
+                )
+            }
+        };
+
+        let response = shorten(serde_json::json!({
+            "url": "https://example.com/?utm_source=blog",
+            "params": {"utm_source": "ignored", "utm_campaign": "autumn"},
+        }))
+        .await;
+        let short = response.json::<ShortenResponse>().await.unwrap().short;
+        assert!(
+            location(short).await
+                == (
+                    StatusCode::MOVED_PERMANENTLY,
+                    "https://example.com/?utm_source=blog&utm_campaign=autumn".to_string()
+                )
+        );
+
+        let response = shorten(serde_json::json!({
+            "url": "https://example.com/",
+            "param_template": "newsletter",
+            "params": {"utm_medium": "sms"},
+        }))
+        .await;
+        let short = response.json::<ShortenResponse>().await.unwrap().short;
+        assert!(
+            location(short).await
+                == (
+                    StatusCode::FOUND,
+                    "https://example.com/?utm_medium=sms&utm_source=newsletter".to_string()
+                )
+        );
+
+        let response = shorten(serde_json::json!({
+            "url": "https://example.com/",
+            "param_template": "ads",
+        }))
+        .await;
+        assert!(response.status() == StatusCode::BAD_REQUEST);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+    std::fs::remove_file(&templates).unwrap();
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test]
+async fn preview_and_interstitial() {
+    logger_init();
+
+    let (conn, app) = app_init_with(Config {
+        admin_token: Some("s3cret-token".to_string()),
+        ..inmemory_random_config()
+    })
+    .await;
+
+    let script = async {
+        let base = format!("http://{}:{}", conn.host, conn.port);
+        conn.client
+            .post(format!("{base}/api/v1/"))
+            .json(&serde_json::json!({"url": "https://example.com/?q=<b>", "alias": "preview_me"}))
+            .send()
+            .await
+            .unwrap();
+
+        for url in [
+            format!("{base}/api/v1/preview_me+"),
+            format!("{base}/api/v1/preview_me?preview"),
+        ] {
+            let response = conn.client.get(url).send().await.unwrap();
+            assert!(response.status() == StatusCode::OK);
+            let page = response.text().await.unwrap();
+            assert!(page.contains("https://example.com/?q=%3Cb%3E"));
+            // Resolves next to the page, `/s/preview_me?continue=...` behind nginx
+            assert!(page.contains("href=\"preview_me?continue="));
+            assert!(!page.contains("Warning"));
+        }
+
+        let force = |token: &'static str| {
+            conn.client
+                .put(format!("{base}/admin/interstitial/preview_me"))
+                .bearer_auth(token)
+                .json(&serde_json::json!({"message": "Reported as phishing"}))
+                .send()
+        };
+        assert!(force("wrong").await.unwrap().status() == StatusCode::UNAUTHORIZED);
+        assert!(force("s3cret-token").await.unwrap().status() == StatusCode::NO_CONTENT);
+
+        let response = conn
+            .client
+            .get(format!("{base}/api/v1/preview_me"))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::OK);
+        let page = response.text().await.unwrap();
+        assert!(page.contains("Reported as phishing"));
+        let continue_to = page
+            .split("href=\"")
+            .find_map(|rest| rest.strip_prefix("preview_me?continue="))
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+
+        // Continue links cannot be made up, so the warning cannot be skipped by sharing one
+        for token in ["", "1", "99999999999.00"] {
+            let response = conn
+                .client
+                .get(format!("{base}/api/v1/preview_me?continue={token}"))
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status() == StatusCode::OK);
+        }
+        let response = conn
+            .client
+            .get(format!("{base}/api/v1/preview_me?continue={continue_to}"))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::MOVED_PERMANENTLY);
+
+        let response = conn
+            .client
+            .delete(format!("{base}/admin/interstitial/preview_me"))
+            .bearer_auth("s3cret-token")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::NO_CONTENT);
+        let response = conn
+            .client
+            .get(format!("{base}/api/v1/preview_me"))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::MOVED_PERMANENTLY);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test]
+async fn qr_code() {
+    logger_init();
+
+    let (conn, app) = app_init_with(Config {
+        public_base_url: Some("https://sho.rt".to_string()),
+        ..inmemory_random_config()
+    })
+    .await;
+
+    let script = async {
+        let base = format!("http://{}:{}", conn.host, conn.port);
+        conn.client
+            .post(format!("{base}/api/v1/"))
+            .json(&serde_json::json!({"url": "https://example.com/", "alias": "poster"}))
+            .send()
+            .await
+            .unwrap();
+
+        let response = conn
+            .client
+            .get(format!("{base}/api/v1/poster/qr"))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::OK);
+        assert!(response.headers()["Content-Type"] == "image/svg+xml");
+        let etag = response.headers()["ETag"].clone();
+        // Encodes the link as nginx serves it
+        let options = QrOptions::default();
+        let public_url = "https://sho.rt/s/poster";
+        assert!(etag == options.etag(public_url).as_str());
+        assert!(response.bytes().await.unwrap() == qr::render(public_url, &options).unwrap());
+
+        let response = conn
+            .client
+            .get(format!("{base}/api/v1/poster/qr"))
+            .header("If-None-Match", etag.clone())
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::NOT_MODIFIED);
+
+        let response = conn
+            .client
+            .get(format!(
+                "{base}/api/v1/poster/qr?format=png&size=128&margin=2&ecc=H&fg=%23003366"
+            ))
+            .header("If-None-Match", etag)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::OK);
+        assert!(response.headers()["Content-Type"] == "image/png");
+        assert!(response.bytes().await.unwrap().starts_with(b"\x89PNG"));
+
+        for (url, status) in [
+            ("/api/v1/poster/qr?size=5000", StatusCode::BAD_REQUEST),
+            ("/api/v1/poster/qr?format=gif", StatusCode::BAD_REQUEST),
+            ("/api/v1/missing/qr", StatusCode::NOT_FOUND),
+        ] {
+            let response = conn
+                .client
+                .get(format!("{base}{url}"))
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status() == status, "{url}");
+        }
+        // Links themselves still redirect
+        let response = conn
+            .client
+            .get(format!("{base}/api/v1/poster"))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::MOVED_PERMANENTLY);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test]
+async fn namespaces() {
+    logger_init();
+
+    let (conn, app) = app_init_with(Config {
+        admin_token: Some("s3cret-token".to_string()),
+        ..inmemory_random_config()
+    })
+    .await;
+
+    let script = async {
+        let base = format!("http://{}:{}", conn.host, conn.port);
+        let create_namespace = |body: serde_json::Value| {
+            conn.client
+                .post(format!("{base}/admin/namespaces"))
+                .bearer_auth("s3cret-token")
+                .json(&body)
+                .send()
+        };
+        let response = create_namespace(serde_json::json!({"name": "docs", "max_links": 2}))
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::CREATED);
+        let created: serde_json::Value = response.json().await.unwrap();
+        let api_key = created["api_key"].as_str().unwrap().to_string();
+        assert!(api_key.starts_with("rurl_"));
+        for (body, status) in [
+            (serde_json::json!({"name": "docs"}), StatusCode::CONFLICT),
+            (
+                serde_json::json!({"name": "Docs!"}),
+                StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            assert!(create_namespace(body).await.unwrap().status() == status);
+        }
+
+        let shorten = |key: &str, alias: &str| {
+            conn.client
+                .post(format!("{base}/t/docs/"))
+                .bearer_auth(key)
+                .json(&serde_json::json!({"url": "https://example.com/docs", "alias": alias}))
+                .send()
+        };
+        assert!(shorten("rurl_wrong", "wiki").await.unwrap().status() == StatusCode::UNAUTHORIZED);
+        assert!(shorten(&api_key, "wiki").await.unwrap().status() == StatusCode::OK);
+        // Links which were not created leave the quota as it was
+        assert!(shorten(&api_key, "wiki").await.unwrap().status() == StatusCode::CONFLICT);
+        assert!(shorten(&api_key, "no!").await.unwrap().status() == StatusCode::BAD_REQUEST);
+        // The same alias outside of the namespace is a different link
+        let response = conn
+            .client
+            .post(format!("{base}/api/v1/"))
+            .json(&serde_json::json!({"url": "https://example.com/other", "alias": "wiki"}))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::OK);
+
+        for (url, location) in [
+            ("/t/docs/wiki", "https://example.com/docs"),
+            ("/api/v1/wiki", "https://example.com/other"),
+        ] {
+            let response = conn
+                .client
+                .get(format!("{base}{url}"))
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status() == StatusCode::MOVED_PERMANENTLY);
+            assert!(response.headers()["Location"] == location);
+        }
+        for url in ["/api/v1/docs/wiki", "/t/other/wiki"] {
+            let response = conn
+                .client
+                .get(format!("{base}{url}"))
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status() == StatusCode::NOT_FOUND, "{url}");
+        }
+
+        // Generated links count towards the quota as well
+        let response = conn
+            .client
+            .post(format!("{base}/t/docs/"))
+            .bearer_auth(&api_key)
+            .json(&serde_json::json!({"url": "https://example.com/generated"}))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::OK);
+        assert!(
+            shorten(&api_key, "third").await.unwrap().status() == StatusCode::TOO_MANY_REQUESTS
+        );
+
+        let namespaces: serde_json::Value = conn
+            .client
+            .get(format!("{base}/admin/namespaces"))
+            .bearer_auth("s3cret-token")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(namespaces[0]["name"] == "docs" && namespaces[0]["max_links"] == 2);
+        assert!(namespaces[0].get("api_key_hash").is_none());
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}
+
+#[tokio::test]
+async fn custom_domains() {
+    logger_init();
+
+    let dir = std::env::temp_dir();
+    let not_found_page = dir.join(format!("rurl-acme-404-{}.html", std::process::id()));
+    std::fs::write(&not_found_page, "<h1>Nothing at Acme</h1>").unwrap();
+    let domains_file = dir.join(format!("rurl-domains-{}.toml", std::process::id()));
+    std::fs::write(
+        &domains_file,
+        format!(
+            "[\"go.acme.com\"]\nredirect_code = 302\nnot_found_page = {:?}\n\n[\"acme.link\"]\n",
+            not_found_page.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    let (conn, app) = app_init_with(Config {
+        domains_file: Some(domains_file.to_string_lossy().to_string()),
+        ..inmemory_random_config()
+    })
+    .await;
+    std::fs::remove_file(&domains_file).unwrap();
+    std::fs::remove_file(&not_found_page).unwrap();
+
+    let script = async {
+        let base = format!("http://{}:{}", conn.host, conn.port);
+        let shorten = |domain: Option<&str>, url: &str| {
+            conn.client
+                .post(format!("{base}/api/v1/"))
+                .json(&serde_json::json!({"url": url, "alias": "docs", "domain": domain}))
+                .send()
+        };
+        for (domain, url) in [
+            (Some("go.acme.com"), "https://acme.com/docs"),
+            (Some("Acme.Link"), "https://acme.com/link-docs"),
+            (None, "https://example.com/docs"),
+        ] {
+            assert!(shorten(domain, url).await.unwrap().status() == StatusCode::OK);
+        }
+        assert!(
+            shorten(Some("evil.com"), "https://example.com/")
+                .await
+                .unwrap()
+                .status()
+                == StatusCode::BAD_REQUEST
+        );
+        // Links to a branded domain are unwrapped through its own table
+        let response = conn
+            .client
+            .post(format!("{base}/api/v1/"))
+            .json(&serde_json::json!({"url": "https://acme.link/api/v1/docs", "alias": "via_acme"}))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::OK);
+
+        for (host, path, status, location) in [
+            (
+                "go.acme.com",
+                "docs",
+                StatusCode::FOUND,
+                "https://acme.com/docs",
+            ),
+            (
+                "acme.link:4444",
+                "docs",
+                StatusCode::MOVED_PERMANENTLY,
+                "https://acme.com/link-docs",
+            ),
+            (
+                "localhost",
+                "docs",
+                StatusCode::MOVED_PERMANENTLY,
+                "https://example.com/docs",
+            ),
+            (
+                "localhost",
+                "via_acme",
+                StatusCode::MOVED_PERMANENTLY,
+                "https://acme.com/link-docs",
+            ),
+        ] {
+            let response = conn
+                .client
+                .get(format!("{base}/api/v1/{path}"))
+                .header("Host", host)
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status() == status, "{host}/{path}");
+            assert!(response.headers()["Location"] == location, "{host}/{path}");
+        }
+
+        let response = conn
+            .client
+            .get(format!("{base}/api/v1/via_acme"))
+            .header("Host", "go.acme.com")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::NOT_FOUND);
+        assert!(response.text().await.unwrap() == "<h1>Nothing at Acme</h1>");
+        let response = conn
+            .client
+            .get(format!("{base}/api/v1/missing"))
+            .header("Host", "acme.link")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::NOT_FOUND);
+    };
+
+    let (_, script_res) = tokio::join!(
+        timeout(Duration::from_secs(1), app.run()),
+        timeout(Duration::from_secs(1), script)
+    );
+
+    assert!(script_res.is_ok());
+}